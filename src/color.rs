@@ -0,0 +1,84 @@
+/// A color as it is stored in a cell. Colors are only turned into actual RGBA values by the palette when drawing, so
+/// that changing the palette also changes the colors of the text that is already on the screen.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Color {
+    DefaultForeground,
+    DefaultBackground,
+    Indexed(u8), // An index into the xterm 256-color table, the first 16 of them are the ANSI colors of the palette.
+    Rgb(u8, u8, u8),
+}
+
+/// The colors used for drawing the terminal.
+#[derive(Clone, Debug)]
+pub struct Palette {
+    pub foreground: [f32; 4],
+    pub background: [f32; 4],
+    pub cursor: [f32; 4],
+    pub ansi_colors: [[f32; 4]; 16], // The first 8 are the normal and the last 8 are the bright variants.
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette {
+            foreground: [0.9, 0.5, 0.5, 1.0],
+            background: [0.38, 0.235, 0.027, 1.],
+            cursor: [0.6, 0.6, 0.5, 0.5],
+            // The default colors of xterm.
+            ansi_colors: [
+                rgb(0, 0, 0),       // Black.
+                rgb(205, 0, 0),     // Red.
+                rgb(0, 205, 0),     // Green.
+                rgb(205, 205, 0),   // Yellow.
+                rgb(0, 0, 238),     // Blue.
+                rgb(205, 0, 205),   // Magenta.
+                rgb(0, 205, 205),   // Cyan.
+                rgb(229, 229, 229), // White.
+                rgb(127, 127, 127), // Bright black.
+                rgb(255, 0, 0),     // Bright red.
+                rgb(0, 255, 0),     // Bright green.
+                rgb(255, 255, 0),   // Bright yellow.
+                rgb(92, 92, 255),   // Bright blue.
+                rgb(255, 0, 255),   // Bright magenta.
+                rgb(0, 255, 255),   // Bright cyan.
+                rgb(255, 255, 255), // Bright white.
+            ],
+        }
+    }
+}
+
+impl Palette {
+    pub fn resolve(&self, color: Color) -> [f32; 4] {
+        match color {
+            Color::DefaultForeground => self.foreground,
+            Color::DefaultBackground => self.background,
+            Color::Indexed(index) => self.indexed(index),
+            Color::Rgb(r, g, b) => rgb(r, g, b),
+        }
+    }
+
+    /// The color with the given index in the xterm 256-color table: the 16 ANSI colors, followed by a 6x6x6 color cube
+    /// and a grayscale ramp of 24 steps.
+    fn indexed(&self, index: u8) -> [f32; 4] {
+        match index {
+            0..=15 => self.ansi_colors[index as usize],
+            16..=231 => {
+                let cube_index = index - 16;
+                let level = |value: u8| if value == 0 { 0 } else { 55 + value * 40 };
+
+                rgb(
+                    level(cube_index / 36),
+                    level((cube_index / 6) % 6),
+                    level(cube_index % 6),
+                )
+            }
+            232..=255 => {
+                let gray = 8 + (index - 232) * 10;
+                rgb(gray, gray, gray)
+            }
+        }
+    }
+}
+
+pub fn rgb(r: u8, g: u8, b: u8) -> [f32; 4] {
+    [r as f32 / 255., g as f32 / 255., b as f32 / 255., 1.]
+}
@@ -1,5 +1,7 @@
+mod color;
 mod context;
 mod performer;
+mod rectangle;
 mod screen;
 mod state;
 mod utils;
@@ -1,81 +1,138 @@
-use std::{os::fd::OwnedFd, sync::Arc};
-
-use glyph_brush::{ab_glyph::FontRef, OwnedSection};
-use glyph_brush::{Layout, Section, Text};
-use vte::{Params, Perform};
-use wgpu_text::TextBrush;
-use winit::window::Window;
-
-use crate::screen::Screen;
-use crate::utils;
-
-pub struct Performer<'a> {
-    pub window: Option<Arc<Window>>,
-    pub font: &'a Vec<u8>,
-    pub brush: Option<TextBrush<FontRef<'a>>>,
-    pub char_width: f32,
-    pub cursor_index: usize,
-
-    pub font_size: f32,
-    pub font_color: [f32; 4],
-    pub text_offset_from_left: f32,
-    pub text_offset_from_top_as_percentage: f32,
-    pub cursor_section: Option<OwnedSection>, // Our cursor section (the unicode character "█").
+use vte::{Params, ParamsIter, Perform};
+
+use crate::color::Color;
+use crate::screen::{Attributes, Cell, Charset, Screen};
+
+/// The Performer drives our terminal state (the Screen) based on what the vte parser reads from the shell.
+pub struct Performer {
     pub screen: Screen,
-    pub pty_fd: &'a OwnedFd, // We will write to this file descriptor, what we write here will be read by the shell on the other side.
+    pub setting_request: Option<Vec<u8>>, // The setting requested with DECRQSS, collected while the DCS string is read.
+    pub responses: Vec<u8>, // Responses to queries of the application, waiting to be written to the pty.
 }
 
-impl<'a> Perform for Performer<'a> {
+impl Performer {
+    pub fn new(screen: Screen) -> Self {
+        Performer {
+            screen,
+            setting_request: None,
+            responses: Vec::new(),
+        }
+    }
+
+    /// Queue a response to a query of the application, to be written back to the pty after the input is parsed.
+    fn respond(&mut self, response: &[u8]) {
+        self.responses.extend_from_slice(response);
+    }
+
+    /// Answer a DECRQSS request with the current value of the requested setting, or report it as invalid if it is not
+    /// supported.
+    fn report_setting(&mut self, setting_request: &[u8]) {
+        let setting = match setting_request {
+            // The current graphic rendition.
+            b"m" => Some(format!("{}m", sgr_parameters(&self.screen.pen))),
+            // The scroll region.
+            b"r" => Some(format!(
+                "{};{}r",
+                self.screen.scroll_top + 1,
+                self.screen.scroll_bottom + 1
+            )),
+            _ => None,
+        };
+
+        match setting {
+            Some(setting) => self.respond(format!("\x1bP1$r{setting}\x1b\\").as_bytes()),
+            None => self.respond(b"\x1bP0$r\x1b\\"),
+        }
+    }
+
+    /// Set or reset a DEC private mode, ignoring the modes that are not supported (see Modes::private_mode_mut()).
+    fn set_private_mode(&mut self, mode: u16, enabled: bool) {
+        let Some(flag) = self.screen.modes.private_mode_mut(mode) else {
+            return;
+        };
+
+        match mode {
+            6 => {
+                // Origin mode, the cursor goes to the new home position.
+                *flag = enabled;
+                self.screen.set_cursor_position(0, 0);
+            }
+            // Alternate screen buffer.
+            47 => {
+                if enabled {
+                    self.screen.enter_alternate_screen();
+                } else {
+                    self.screen.leave_alternate_screen();
+                }
+            }
+            // Alternate screen buffer, which is cleared when leaving it.
+            1047 => {
+                if enabled {
+                    self.screen.enter_alternate_screen();
+                } else {
+                    if self.screen.modes.alternate_screen {
+                        self.screen.erase_all();
+                    }
+                    self.screen.leave_alternate_screen();
+                }
+            }
+            // Save the cursor and switch to the cleared alternate screen buffer, and the other way around when leaving it.
+            1049 => {
+                if enabled {
+                    self.screen.save_cursor();
+                    self.screen.enter_alternate_screen();
+                    self.screen.erase_all();
+                } else {
+                    self.screen.leave_alternate_screen();
+                    self.screen.restore_cursor();
+                }
+            }
+            _ => *flag = enabled,
+        }
+    }
+}
+
+impl Perform for Performer {
     fn print(&mut self, c: char) {
         let screen = &mut self.screen;
+        let charset_index = screen.single_shift.take().unwrap_or(screen.active_charset);
 
-        screen.glyphs[screen.row_index][screen.column_index].text[0].text = String::from(c);
-        screen.glyphs[screen.row_index][screen.column_index].text[0]
-            .extra
-            .color = self.font_color;
-        screen.column_index += 1;
+        let c = match screen.charsets[charset_index] {
+            Charset::Ascii => c,
+            Charset::Uk if c == '#' => '£',
+            Charset::Uk => c,
+            Charset::DecSpecialGraphics => dec_special_graphics(c),
+        };
 
-        utils::move_cursor_right(self);
-        self.cursor_index += 1;
+        screen.print(c);
     }
 
     fn execute(&mut self, byte: u8) {
-        println!("This is execute: {byte}");
         match byte {
-            b'\n' => {
-                let screen = &mut self.screen;
-
+            // Vertical tab and form feed are treated the same as a line feed.
+            b'\n' | 0x0B | 0x0C => {
                 // Go down to the next row.
-                screen.row_index += 1;
-
-                self.cursor_index += 1;
-
-                // Move cursor visually to the next line.
-                let cursor = self.cursor_section.as_mut().unwrap();
-                cursor.screen_position.0 = self.text_offset_from_left;
-                cursor.screen_position.1 += self.font_size;
+                self.screen.linefeed();
             }
             b'\r' => {
                 // Carriage return: move to start of the line.
-                self.screen.column_index = 0;
-                self.cursor_index = 0;
+                self.screen.carriage_return();
             }
             0x08 => {
                 // Backspace.
-                if self.cursor_index > 0 {
-                    // Move the cursor.
-                    self.cursor_index -= 1;
-                    utils::move_cursor_left(self);
-
-                    // Delete the character from the screen.
-                    let screen = &mut self.screen;
-
-                    if screen.column_index > 0 {
-                        screen.column_index -= 1;
-                        screen.glyphs[screen.row_index][screen.column_index].text[0].text =
-                            String::from("");
-                    }
-                }
+                self.screen.backspace();
+            }
+            0x0E => {
+                // Shift out: use G1 for printing.
+                self.screen.active_charset = 1;
+            }
+            0x0F => {
+                // Shift in: use G0 for printing.
+                self.screen.active_charset = 0;
+            }
+            b'\t' => {
+                // Move to the next tab stop.
+                self.screen.tab(1);
             }
             _ => {
                 // Unhandled control byte. TODO: Improve this.
@@ -83,110 +140,276 @@ impl<'a> Perform for Performer<'a> {
         }
     }
 
-    fn csi_dispatch(
-        &mut self,
-        params: &Params,
-        _intermediates: &[u8],
-        _ignore: bool,
-        action: char,
-    ) {
+    fn csi_dispatch(&mut self, params: &Params, intermediates: &[u8], _ignore: bool, action: char) {
         //println!("This is the csi_dispatch: {}", action);
         match action {
-            // Change font color.
-            'm' => {
-                for param in params.iter() {
+            // Change font color and attributes.
+            'm' if intermediates.is_empty() => {
+                let mut params = params.iter();
+                while let Some(param) = params.next() {
                     match param {
                         [0] => {
-                            self.font_color = [1., 1., 1., 1.]; // Make font color white (this is the reset option).
+                            // This is the reset option, use the default colors and remove all the attributes.
+                            self.screen.pen.fg = Color::DefaultForeground;
+                            self.screen.pen.bg = Color::DefaultBackground;
+                            self.screen.pen.attributes = Attributes::default();
                         }
-                        [1] => {}
-                        [30] => {
-                            self.font_color = [0., 0., 0., 1.]; // Make font color black.
+                        [1] => self.screen.pen.attributes.bold = true,
+                        [2] => self.screen.pen.attributes.dim = true,
+                        [3] => self.screen.pen.attributes.italic = true,
+                        // "4:0" is the colon separated form for no underline, other underline styles are drawn as a single underline.
+                        [4, 0] => self.screen.pen.attributes.underline = false,
+                        [4, ..] | [21] => self.screen.pen.attributes.underline = true,
+                        [5] | [6] => self.screen.pen.attributes.blink = true,
+                        [7] => self.screen.pen.attributes.inverse = true,
+                        [8] => self.screen.pen.attributes.hidden = true,
+                        [9] => self.screen.pen.attributes.strikethrough = true,
+                        [22] => {
+                            // Normal intensity, neither bold nor dim.
+                            self.screen.pen.attributes.bold = false;
+                            self.screen.pen.attributes.dim = false;
                         }
-                        [31] => {
-                            self.font_color = [1., 0., 0., 1.]; // Make font color red.
+                        [23] => self.screen.pen.attributes.italic = false,
+                        [24] => self.screen.pen.attributes.underline = false,
+                        [25] => self.screen.pen.attributes.blink = false,
+                        [27] => self.screen.pen.attributes.inverse = false,
+                        [28] => self.screen.pen.attributes.hidden = false,
+                        [29] => self.screen.pen.attributes.strikethrough = false,
+                        [n @ 30..=37] => {
+                            self.screen.pen.fg = Color::Indexed((n - 30) as u8);
                         }
-                        [32] => {
-                            self.font_color = [0., 1., 0., 1.]; // Make font color green.
+                        [38, subparams @ ..] => {
+                            // 256-color or truecolor font color.
+                            if let Some(color) = extended_color(subparams, &mut params) {
+                                self.screen.pen.fg = color;
+                            }
                         }
-                        [33] => {
-                            self.font_color = [1., 1., 0., 1.]; // Make font color yellow.
+                        [39] => {
+                            self.screen.pen.fg = Color::DefaultForeground;
                         }
-                        [34] => {
-                            self.font_color = [0., 0., 1., 1.]; // Make font color blue.
+                        [n @ 40..=47] => {
+                            self.screen.pen.bg = Color::Indexed((n - 40) as u8);
                         }
-                        [35] => {
-                            self.font_color = [1., 0., 1., 1.]; // Make font color magenta.
+                        [48, subparams @ ..] => {
+                            // 256-color or truecolor background color.
+                            if let Some(color) = extended_color(subparams, &mut params) {
+                                self.screen.pen.bg = color;
+                            }
                         }
-                        [36] => {
-                            self.font_color = [0., 1., 1., 1.]; // Make font color cyan.
+                        [49] => {
+                            self.screen.pen.bg = Color::DefaultBackground;
                         }
-                        [37] => {
-                            self.font_color = [1., 1., 1., 1.]; // Make font color white.
+                        [n @ 90..=97] => {
+                            // Bright font colors.
+                            self.screen.pen.fg = Color::Indexed((n - 90 + 8) as u8);
                         }
-                        [39] => {
-                            self.font_color = [1., 1., 1., 1.]; // Make font color white (this is the default option).
+                        [n @ 100..=107] => {
+                            // Bright background colors.
+                            self.screen.pen.bg = Color::Indexed((n - 100 + 8) as u8);
                         }
                         _ => (),
                     }
                 }
             }
+            // Move the cursor up.
+            'A' => self.screen.move_cursor_up(param_or(params, 0, 1)),
+            // Move the cursor down.
+            'B' => self.screen.move_cursor_down(param_or(params, 0, 1)),
             // Move the cursor right.
-            'C' => {}
+            'C' => self.screen.move_cursor_right(param_or(params, 0, 1)),
             // Move the cursor left.
-            'D' => {
-                // TODO.
-                println!("I am at cursor left!");
-                let offset = params.iter().flatten().next().copied().unwrap_or(1);
-                self.cursor_index = self.cursor_index.saturating_sub(offset as usize);
-
-                for _ in 0..offset {
-                    utils::move_cursor_left(self);
+            'D' => self.screen.move_cursor_left(param_or(params, 0, 1)),
+            // Move the cursor to the beginning of the line, some lines down.
+            'E' => {
+                self.screen.move_cursor_down(param_or(params, 0, 1));
+                self.screen.carriage_return();
+            }
+            // Move the cursor to the beginning of the line, some lines up.
+            'F' => {
+                self.screen.move_cursor_up(param_or(params, 0, 1));
+                self.screen.carriage_return();
+            }
+            // Move the cursor to the given column (the parameters are 1-based, our indices are 0-based).
+            'G' | '`' => {
+                let col = param_or(params, 0, 1) - 1;
+                self.screen.move_cursor_to(self.screen.row_index, col);
+            }
+            // Move the cursor to the given row.
+            'd' => {
+                let row = param_or(params, 0, 1) - 1;
+                self.screen
+                    .set_cursor_position(row, self.screen.column_index);
+            }
+            // Move the cursor to the given row and column.
+            'H' | 'f' => {
+                let row = param_or(params, 0, 1) - 1;
+                let col = param_or(params, 1, 1) - 1;
+                self.screen.set_cursor_position(row, col);
+            }
+            // Move the cursor forward some tab stops.
+            'I' => self.screen.tab(param_or(params, 0, 1)),
+            // Move the cursor back some tab stops.
+            'Z' => self.screen.back_tab(param_or(params, 0, 1)),
+            // Clear tab stops.
+            'g' => match param_or(params, 0, 0) {
+                0 => self.screen.clear_tab_stop(),
+                3 => self.screen.clear_all_tab_stops(),
+                _ => (),
+            },
+            // Erase in line.
+            'K' => match param_or(params, 0, 0) {
+                0 => self.screen.erase_line_right(),
+                1 => self.screen.erase_line_left(),
+                2 => self.screen.erase_line(),
+                _ => (),
+            },
+            // Erase in display.
+            'J' => match param_or(params, 0, 0) {
+                0 => self.screen.erase_below(),
+                1 => self.screen.erase_above(),
+                2 => self.screen.erase_all(),
+                3 => self.screen.clear_scrollback(),
+                _ => (),
+            },
+            // Erase characters.
+            'X' => self.screen.erase_characters(param_or(params, 0, 1)),
+            // Insert characters.
+            '@' => self.screen.insert_characters(param_or(params, 0, 1)),
+            // Delete characters.
+            'P' => self.screen.delete_characters(param_or(params, 0, 1)),
+            // Insert lines.
+            'L' => self.screen.insert_lines(param_or(params, 0, 1)),
+            // Delete lines.
+            'M' => self.screen.delete_lines(param_or(params, 0, 1)),
+            // Device status report.
+            'n' => {
+                // The cursor position is reported 1-based.
+                let row = self.screen.cursor_row() + 1;
+                let col = self.screen.column_index + 1;
+
+                match (intermediates, param_or(params, 0, 0)) {
+                    // Status report, we are always OK.
+                    ([], 5) => self.respond(b"\x1b[0n"),
+                    // Cursor position report.
+                    ([], 6) => self.respond(format!("\x1b[{row};{col}R").as_bytes()),
+                    // Extended cursor position report (DECXCPR).
+                    ([b'?'], 6) => self.respond(format!("\x1b[?{row};{col}R").as_bytes()),
+                    _ => (),
                 }
             }
-            // Delete a single character in the line.
-            'K' => {}
-            'J' => {
+            // Device attributes.
+            'c' => match intermediates {
+                // Primary device attributes: we are a VT220 with ANSI colors.
+                [] => self.respond(b"\x1b[?62;22c"),
+                // Secondary device attributes: terminal type, firmware version (the crate version) and ROM cartridge.
+                [b'>'] => self.respond(format!("\x1b[>1;{};0c", version_number()).as_bytes()),
+                // Tertiary device attributes: the unit id.
+                [b'='] => self.respond(b"\x1bP!|00000000\x1b\\"),
+                _ => (),
+            },
+            // Report the name and the version of the terminal (XTVERSION).
+            'q' if intermediates == [b'>'] => {
+                let version = env!("CARGO_PKG_VERSION");
+                self.respond(format!("\x1bP>|rustole {version}\x1b\\").as_bytes());
+            }
+            // Request the state of a mode (DECRQM). The reported state is 1 for set, 2 for reset and 0 for modes that we
+            // do not support.
+            'p' if intermediates == [b'?', b'$'] => {
+                let mode = param_or(params, 0, 0) as u16;
+                let state = match self.screen.modes.private_mode(mode) {
+                    Some(true) => 1,
+                    Some(false) => 2,
+                    None => 0,
+                };
+                self.respond(format!("\x1b[?{mode};{state}$y").as_bytes());
+            }
+            'p' if intermediates == [b'$'] => {
+                // We do not support any of the ANSI modes.
+                let mode = param_or(params, 0, 0);
+                self.respond(format!("\x1b[{mode};0$y").as_bytes());
+            }
+            // Set or reset DEC private modes.
+            'h' | 'l' if intermediates == [b'?'] => {
                 for param in params.iter() {
-                    match param {
-                        [0] => todo!(),
-                        [1] => todo!(),
-                        [2] => {
-                            // This means we have to clear the entire screen.
-                            let screen = &mut self.screen;
-                            for line in &mut screen.glyphs {
-                                for glyph in line {
-                                    glyph.text[0].text = String::from("");
-                                }
-                            }
-
-                            self.screen.row_index = 0;
-                            self.screen.column_index = 0;
-
-                            // Reset the cursor section position.
-                            let cursor_section = &mut self.cursor_section.as_mut().unwrap();
-                            cursor_section.screen_position.0 = self.text_offset_from_left;
-                            cursor_section.screen_position.1 = self
-                                .text_offset_from_top_as_percentage
-                                * self.screen.screen_height as f32;
-                        }
-                        _ => (),
-                    }
+                    self.set_private_mode(param[0], action == 'h');
                 }
             }
+            // Save the cursor.
+            's' if intermediates.is_empty() => self.screen.save_cursor(),
+            // Restore the cursor.
+            'u' if intermediates.is_empty() => self.screen.restore_cursor(),
+            // Set the scroll region (1-based and inclusive, by default the whole screen).
+            'r' if intermediates.is_empty() => {
+                let top = param_or(params, 0, 1) - 1;
+                let bottom = param_or(params, 1, self.screen.num_rows()) - 1;
+                self.screen.set_scroll_region(top, bottom);
+            }
+            // Scroll up.
+            'S' if intermediates.is_empty() => self.screen.scroll_up(param_or(params, 0, 1)),
+            // Scroll down.
+            'T' if intermediates.is_empty() => self.screen.scroll_down(param_or(params, 0, 1)),
             _ => (),
         }
     }
 
-    fn esc_dispatch(&mut self, _intermediates: &[u8], _ignore: bool, byte: u8) {
-        println!("This is the last byte of the escape dispatch: {byte}");
+    fn esc_dispatch(&mut self, intermediates: &[u8], _ignore: bool, byte: u8) {
+        match (intermediates, byte) {
+            // Save the cursor (DECSC).
+            ([], b'7') => self.screen.save_cursor(),
+            // Restore the cursor (DECRC).
+            ([], b'8') => self.screen.restore_cursor(),
+            // Application keypad mode (DECKPAM).
+            ([], b'=') => self.screen.modes.application_keypad = true,
+            // Normal keypad mode (DECKPNM).
+            ([], b'>') => self.screen.modes.application_keypad = false,
+            // Set a tab stop at the cursor.
+            ([], b'H') => self.screen.set_tab_stop(),
+            // Designate a character set to G0, G1, G2 or G3.
+            ([designator @ (b'(' | b')' | b'*' | b'+')], charset) => {
+                let index = (designator - b'(') as usize;
+                self.screen.charsets[index] = match charset {
+                    b'0' => Charset::DecSpecialGraphics,
+                    b'A' => Charset::Uk,
+                    _ => Charset::Ascii,
+                };
+            }
+            // Use G2 or G3 for printing (LS2, LS3).
+            ([], b'n') => self.screen.active_charset = 2,
+            ([], b'o') => self.screen.active_charset = 3,
+            // Use G2 or G3 for only the next printed character (SS2, SS3).
+            ([], b'N') => self.screen.single_shift = Some(2),
+            ([], b'O') => self.screen.single_shift = Some(3),
+            // Index.
+            ([], b'D') => self.screen.index(),
+            // Reverse index.
+            ([], b'M') => self.screen.reverse_index(),
+            // Next line.
+            ([], b'E') => {
+                self.screen.index();
+                self.screen.carriage_return();
+            }
+            _ => (),
+        }
     }
 
-    fn hook(&mut self, _params: &Params, _intermediates: &[u8], _ignore: bool, _action: char) {}
+    fn hook(&mut self, _params: &Params, intermediates: &[u8], _ignore: bool, action: char) {
+        // Request status string (DECRQSS), the requested setting follows as the data of the DCS string.
+        if intermediates == [b'$'] && action == 'q' {
+            self.setting_request = Some(vec![]);
+        }
+    }
 
-    fn unhook(&mut self) {}
+    fn unhook(&mut self) {
+        if let Some(setting_request) = self.setting_request.take() {
+            self.report_setting(&setting_request);
+        }
+    }
 
-    fn put(&mut self, _byte: u8) {}
+    fn put(&mut self, byte: u8) {
+        if let Some(setting_request) = self.setting_request.as_mut() {
+            setting_request.push(byte);
+        }
+    }
 
     fn osc_dispatch(&mut self, _params: &[&[u8]], _bell_terminated: bool) {}
 
@@ -194,3 +417,620 @@ impl<'a> Perform for Performer<'a> {
         false
     }
 }
+
+/// Parse the color of an extended color SGR parameter (38 or 48). The color can either be given with colon separated
+/// subparameters ("38:5:n", "38:2:r:g:b" or "38:2:colorspace:r:g:b"), or with the parameters following it ("38;5;n",
+/// "38;2;r;g;b"), in which case they are consumed from the given parameter iterator.
+fn extended_color(subparams: &[u16], params: &mut ParamsIter) -> Option<Color> {
+    let to_u8 = |value: u16| value.min(255) as u8;
+
+    if subparams.is_empty() {
+        let mut next = || {
+            params
+                .next()
+                .and_then(|param| param.first().copied())
+                .map(to_u8)
+        };
+
+        match next()? {
+            5 => Some(Color::Indexed(next()?)),
+            2 => Some(Color::Rgb(next()?, next()?, next()?)),
+            _ => None,
+        }
+    } else {
+        match *subparams {
+            [5, index] => Some(Color::Indexed(to_u8(index))),
+            [2, r, g, b] | [2, _, r, g, b] => Some(Color::Rgb(to_u8(r), to_u8(g), to_u8(b))),
+            _ => None,
+        }
+    }
+}
+
+/// The SGR parameters that select the colors and the attributes of the given cell, starting from a reset.
+fn sgr_parameters(cell: &Cell) -> String {
+    let attributes = &cell.attributes;
+    let mut parameters = vec![String::from("0")];
+
+    for (enabled, parameter) in [
+        (attributes.bold, "1"),
+        (attributes.dim, "2"),
+        (attributes.italic, "3"),
+        (attributes.underline, "4"),
+        (attributes.blink, "5"),
+        (attributes.inverse, "7"),
+        (attributes.hidden, "8"),
+        (attributes.strikethrough, "9"),
+    ] {
+        if enabled {
+            parameters.push(String::from(parameter));
+        }
+    }
+
+    for (color, base) in [(cell.fg, 30), (cell.bg, 40)] {
+        match color {
+            Color::Indexed(index @ 0..=7) => parameters.push((base + index as u16).to_string()),
+            Color::Indexed(index @ 8..=15) => {
+                parameters.push((base + 60 + index as u16 - 8).to_string())
+            }
+            Color::Indexed(index) => parameters.push(format!("{};5;{index}", base + 8)),
+            Color::Rgb(r, g, b) => parameters.push(format!("{};2;{r};{g};{b}", base + 8)),
+            Color::DefaultForeground | Color::DefaultBackground => (),
+        }
+    }
+
+    parameters.join(";")
+}
+
+/// The crate version as a single number, as it is reported in the secondary device attributes (e.g. 1.2.3 is 10203).
+fn version_number() -> u32 {
+    env!("CARGO_PKG_VERSION")
+        .split('.')
+        .take(3)
+        .fold(0, |number, part| {
+            number * 100 + part.parse::<u32>().unwrap_or(0)
+        })
+}
+
+/// The parameter with the given index, or the given default if it is missing or 0 (which means the same as missing for
+/// most control sequences).
+fn param_or(params: &Params, index: usize, default: usize) -> usize {
+    match params.iter().nth(index).and_then(|param| param.first()) {
+        Some(&value) if value != 0 => value as usize,
+        _ => default,
+    }
+}
+
+/// Translate a character to the DEC special graphics character set, which is used for drawing lines and boxes.
+fn dec_special_graphics(c: char) -> char {
+    match c {
+        '_' => ' ',
+        '`' => '◆',
+        'a' => '▒',
+        'b' => '␉',
+        'c' => '␌',
+        'd' => '␍',
+        'e' => '␊',
+        'f' => '°',
+        'g' => '±',
+        'h' => '␤',
+        'i' => '␋',
+        'j' => '┘',
+        'k' => '┐',
+        'l' => '┌',
+        'm' => '└',
+        'n' => '┼',
+        'o' => '⎺',
+        'p' => '⎻',
+        'q' => '─',
+        'r' => '⎼',
+        's' => '⎽',
+        't' => '├',
+        'u' => '┤',
+        'v' => '┴',
+        'w' => '┬',
+        'x' => '│',
+        'y' => '≤',
+        'z' => '≥',
+        '{' => 'π',
+        '|' => '≠',
+        '}' => '£',
+        '~' => '·',
+        _ => c,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use vte::Parser;
+
+    /// A performer with a screen of the given size, after the given bytes have been parsed.
+    fn performer_after(num_rows: usize, num_cols: usize, bytes: &[u8]) -> Performer {
+        let mut performer = Performer::new(Screen::new(num_rows, num_cols, 10));
+        Parser::new().advance(&mut performer, bytes);
+        performer
+    }
+
+    fn row_text(performer: &Performer, row: usize) -> String {
+        performer.screen.rows[row]
+            .iter()
+            .filter(|cell| !cell.spacer)
+            .map(Cell::grapheme)
+            .collect()
+    }
+
+    #[test]
+    fn parsed_text_and_control_characters_drive_the_screen() {
+        let performer = performer_after(3, 5, b"ab\r\ncd\x08e");
+
+        assert_eq!(row_text(&performer, 0), "ab   ");
+        assert_eq!(row_text(&performer, 1), "ce   ");
+        assert_eq!(
+            (performer.screen.row_index, performer.screen.column_index),
+            (1, 2)
+        );
+        assert!(performer.responses.is_empty());
+    }
+
+    #[test]
+    fn sgr_sets_and_resets_attributes_and_colors() {
+        let performer = performer_after(3, 5, b"\x1b[1;3;4;7;31;42m");
+        let pen = &performer.screen.pen;
+
+        assert!(pen.attributes.bold && pen.attributes.italic);
+        assert!(pen.attributes.underline && pen.attributes.inverse);
+        assert_eq!(pen.fg, Color::Indexed(1));
+        assert_eq!(pen.bg, Color::Indexed(2));
+
+        let performer = performer_after(3, 5, b"\x1b[1;2;9;95;104m\x1b[22;29;39m");
+        let pen = &performer.screen.pen;
+
+        assert_eq!(pen.attributes, Attributes::default());
+        assert_eq!(pen.fg, Color::DefaultForeground);
+        assert_eq!(pen.bg, Color::Indexed(12));
+
+        let performer = performer_after(3, 5, b"\x1b[1;4;31;42m\x1b[m");
+        let pen = &performer.screen.pen;
+
+        assert_eq!(pen.attributes, Attributes::default());
+        assert_eq!(pen.fg, Color::DefaultForeground);
+        assert_eq!(pen.bg, Color::DefaultBackground);
+    }
+
+    #[test]
+    fn underline_styles_are_drawn_as_a_single_underline() {
+        let performer = performer_after(3, 5, b"\x1b[4:3m");
+        assert!(performer.screen.pen.attributes.underline);
+
+        let performer = performer_after(3, 5, b"\x1b[4m\x1b[4:0m");
+        assert!(!performer.screen.pen.attributes.underline);
+    }
+
+    #[test]
+    fn private_sgr_sequences_are_ignored() {
+        let performer = performer_after(3, 5, b"\x1b[>4;2m\x1b[?4m");
+
+        assert_eq!(performer.screen.pen.attributes, Attributes::default());
+    }
+
+    #[test]
+    fn extended_colors_in_semicolon_form() {
+        let performer = performer_after(3, 5, b"\x1b[38;5;208;48;2;10;20;30;1m");
+        let pen = &performer.screen.pen;
+
+        assert_eq!(pen.fg, Color::Indexed(208));
+        assert_eq!(pen.bg, Color::Rgb(10, 20, 30));
+        // The parameters after the color are still applied.
+        assert!(pen.attributes.bold);
+    }
+
+    #[test]
+    fn extended_colors_in_colon_form() {
+        let performer = performer_after(3, 5, b"\x1b[38:2:1:2:3;48:5:17m");
+        assert_eq!(performer.screen.pen.fg, Color::Rgb(1, 2, 3));
+        assert_eq!(performer.screen.pen.bg, Color::Indexed(17));
+
+        // With a color space id.
+        let performer = performer_after(3, 5, b"\x1b[38:2::4:5:6m");
+        assert_eq!(performer.screen.pen.fg, Color::Rgb(4, 5, 6));
+    }
+
+    #[test]
+    fn incomplete_extended_colors_are_ignored() {
+        let performer = performer_after(3, 5, b"\x1b[38;2;1m\x1b[48:5m");
+
+        assert_eq!(performer.screen.pen.fg, Color::DefaultForeground);
+        assert_eq!(performer.screen.pen.bg, Color::DefaultBackground);
+    }
+
+    #[test]
+    fn cursor_movement_sequences() {
+        let screen = performer_after(5, 10, b"\x1b[3;4H").screen;
+        assert_eq!((screen.row_index, screen.column_index), (2, 3));
+
+        let screen = performer_after(5, 10, b"\x1b[3;4H\x1b[2A\x1b[5C").screen;
+        assert_eq!((screen.row_index, screen.column_index), (0, 8));
+
+        let screen = performer_after(5, 10, b"\x1b[3;4H\x1b[B\x1b[2D").screen;
+        assert_eq!((screen.row_index, screen.column_index), (3, 1));
+
+        let screen = performer_after(5, 10, b"\x1b[3;4H\x1b[7G\x1b[2d").screen;
+        assert_eq!((screen.row_index, screen.column_index), (1, 6));
+
+        let screen = performer_after(5, 10, b"\x1b[3;4H\x1b[E").screen;
+        assert_eq!((screen.row_index, screen.column_index), (3, 0));
+
+        let screen = performer_after(5, 10, b"\x1b[3;4H\x1b[2F").screen;
+        assert_eq!((screen.row_index, screen.column_index), (0, 0));
+    }
+
+    #[test]
+    fn cursor_movement_is_clamped_to_the_screen() {
+        let screen = performer_after(5, 10, b"\x1b[99;99H").screen;
+        assert_eq!((screen.row_index, screen.column_index), (4, 9));
+
+        let screen = performer_after(5, 10, b"\x1b[99A\x1b[99D").screen;
+        assert_eq!((screen.row_index, screen.column_index), (0, 0));
+
+        // A parameter of 0 is the same as the default of 1.
+        let screen = performer_after(5, 10, b"\x1b[0;0H\x1b[0C").screen;
+        assert_eq!((screen.row_index, screen.column_index), (0, 1));
+    }
+
+    #[test]
+    fn erase_in_line() {
+        let text = b"abcdef\x1b[1;3H";
+
+        let performer = performer_after(2, 6, &[text.as_slice(), b"\x1b[K"].concat());
+        assert_eq!(row_text(&performer, 0), "ab    ");
+
+        let performer = performer_after(2, 6, &[text.as_slice(), b"\x1b[1K"].concat());
+        assert_eq!(row_text(&performer, 0), "   def");
+
+        let performer = performer_after(2, 6, &[text.as_slice(), b"\x1b[2K"].concat());
+        assert_eq!(row_text(&performer, 0), "      ");
+    }
+
+    #[test]
+    fn erase_in_display() {
+        let text = b"abc\r\ndef\r\nghi\x1b[2;2H";
+
+        let performer = performer_after(3, 3, &[text.as_slice(), b"\x1b[J"].concat());
+        let rows: Vec<String> = (0..3).map(|row| row_text(&performer, row)).collect();
+        assert_eq!(rows, ["abc", "d  ", "   "]);
+
+        let performer = performer_after(3, 3, &[text.as_slice(), b"\x1b[1J"].concat());
+        let rows: Vec<String> = (0..3).map(|row| row_text(&performer, row)).collect();
+        assert_eq!(rows, ["   ", "  f", "ghi"]);
+
+        let performer = performer_after(3, 3, &[text.as_slice(), b"\x1b[2J"].concat());
+        let rows: Vec<String> = (0..3).map(|row| row_text(&performer, row)).collect();
+        assert_eq!(rows, ["   ", "   ", "   "]);
+        // The cursor does not move.
+        assert_eq!(
+            (performer.screen.row_index, performer.screen.column_index),
+            (1, 1)
+        );
+    }
+
+    #[test]
+    fn erase_characters_uses_the_background_color() {
+        let performer = performer_after(2, 6, b"abcdef\x1b[1;2H\x1b[44m\x1b[3X");
+        let row = &performer.screen.rows[0];
+
+        assert_eq!(row_text(&performer, 0), "a   ef");
+        assert_eq!(row[1].bg, Color::Indexed(4));
+        assert_eq!(row[4].bg, Color::DefaultBackground);
+    }
+
+    #[test]
+    fn insert_and_delete_characters() {
+        let performer = performer_after(2, 6, b"abcdef\x1b[1;2H\x1b[2@");
+        assert_eq!(row_text(&performer, 0), "a  bcd");
+
+        let performer = performer_after(2, 6, b"abcdef\x1b[1;2H\x1b[2P");
+        assert_eq!(row_text(&performer, 0), "adef  ");
+
+        // More characters than there are left on the line.
+        let performer = performer_after(2, 6, b"abcdef\x1b[1;5H\x1b[9P");
+        assert_eq!(row_text(&performer, 0), "abcd  ");
+    }
+
+    #[test]
+    fn insert_and_delete_lines() {
+        let text = b"a\r\nb\r\nc\r\nd\x1b[2;1H";
+
+        let performer = performer_after(4, 1, &[text.as_slice(), b"\x1b[L"].concat());
+        let rows: Vec<String> = (0..4).map(|row| row_text(&performer, row)).collect();
+        assert_eq!(rows, ["a", " ", "b", "c"]);
+
+        let performer = performer_after(4, 1, &[text.as_slice(), b"\x1b[2M"].concat());
+        let rows: Vec<String> = (0..4).map(|row| row_text(&performer, row)).collect();
+        assert_eq!(rows, ["a", "d", " ", " "]);
+    }
+
+    #[test]
+    fn scroll_region_and_scrolling_sequences() {
+        let text = b"a\r\nb\r\nc\r\nd";
+
+        // Scroll the middle rows up and down.
+        let performer = performer_after(4, 1, &[text.as_slice(), b"\x1b[2;3r\x1b[S"].concat());
+        let rows: Vec<String> = (0..4).map(|row| row_text(&performer, row)).collect();
+        assert_eq!(rows, ["a", "c", " ", "d"]);
+        assert_eq!(
+            (performer.screen.row_index, performer.screen.column_index),
+            (0, 0)
+        );
+
+        let performer = performer_after(4, 1, &[text.as_slice(), b"\x1b[2;3r\x1b[T"].concat());
+        let rows: Vec<String> = (0..4).map(|row| row_text(&performer, row)).collect();
+        assert_eq!(rows, ["a", " ", "b", "d"]);
+
+        // Without parameters, the scroll region is the whole screen again.
+        let performer = performer_after(4, 1, b"\x1b[2;3r\x1b[r");
+        assert_eq!(
+            (performer.screen.scroll_top, performer.screen.scroll_bottom),
+            (0, 3)
+        );
+    }
+
+    #[test]
+    fn index_and_reverse_index_scroll_at_the_margins() {
+        let text = b"a\r\nb\r\nc\r\nd\x1b[2;3r";
+
+        let performer = performer_after(4, 1, &[text.as_slice(), b"\x1b[3;1H\x1bD"].concat());
+        let rows: Vec<String> = (0..4).map(|row| row_text(&performer, row)).collect();
+        assert_eq!(rows, ["a", "c", " ", "d"]);
+
+        let performer = performer_after(4, 1, &[text.as_slice(), b"\x1b[2;1H\x1bM"].concat());
+        let rows: Vec<String> = (0..4).map(|row| row_text(&performer, row)).collect();
+        assert_eq!(rows, ["a", " ", "b", "d"]);
+    }
+
+    #[test]
+    fn line_feed_at_the_bottom_scrolls_the_screen() {
+        let performer = performer_after(2, 1, b"a\r\nb\r\nc");
+        assert_eq!(row_text(&performer, 0), "b");
+        assert_eq!(row_text(&performer, 1), "c");
+
+        // Vertical tabs and form feeds are line feeds as well.
+        let performer = performer_after(2, 1, b"a\x0b\rb\x0c\rc");
+        assert_eq!(row_text(&performer, 0), "b");
+        assert_eq!(row_text(&performer, 1), "c");
+    }
+
+    #[test]
+    fn auto_wrap_mode_can_be_switched_off() {
+        let performer = performer_after(2, 3, b"\x1b[?7labcd");
+        assert_eq!(row_text(&performer, 0), "abd");
+        assert_eq!(row_text(&performer, 1), "   ");
+
+        let performer = performer_after(2, 3, b"\x1b[?7l\x1b[?7habcd");
+        assert_eq!(row_text(&performer, 1), "d  ");
+    }
+
+    #[test]
+    fn mode_1049_saves_the_cursor_and_clears_the_alternate_screen() {
+        let performer = performer_after(2, 5, b"shell\x1b[1;3H\x1b[?1049h");
+        assert!(performer.screen.modes.alternate_screen);
+        assert_eq!(row_text(&performer, 0), "     ");
+
+        // A cursor saved on the alternate screen does not replace the one saved when entering it.
+        let performer = performer_after(
+            2,
+            5,
+            b"shell\x1b[1;3H\x1b[?1049hvim\x1b[2;5H\x1b7\x1b[?1049l",
+        );
+        assert!(!performer.screen.modes.alternate_screen);
+        assert_eq!(row_text(&performer, 0), "shell");
+        assert_eq!(
+            (performer.screen.row_index, performer.screen.column_index),
+            (0, 2)
+        );
+    }
+
+    #[test]
+    fn mode_1047_clears_the_alternate_screen_when_leaving_it() {
+        let performer = performer_after(2, 5, b"\x1b[?1047hvim\x1b[?1047l\x1b[?47h");
+        assert_eq!(row_text(&performer, 0), "     ");
+
+        // Mode 47 keeps the contents of the alternate screen.
+        let performer = performer_after(2, 5, b"\x1b[?47hvim\x1b[?47l\x1b[?47h");
+        assert_eq!(row_text(&performer, 0), "vim  ");
+    }
+
+    #[test]
+    fn save_and_restore_cursor_with_its_attributes() {
+        let screen = performer_after(5, 10, b"\x1b[2;3H\x1b[1;31m\x1b7\x1b[m\x1b[5;5H\x1b8").screen;
+        assert_eq!((screen.row_index, screen.column_index), (1, 2));
+        assert!(screen.pen.attributes.bold);
+        assert_eq!(screen.pen.fg, Color::Indexed(1));
+
+        let screen = performer_after(5, 10, b"\x1b[2;3H\x1b[s\x1b[5;5H\x1b[u").screen;
+        assert_eq!((screen.row_index, screen.column_index), (1, 2));
+
+        // Without a saved cursor, the cursor goes home and the pen is reset.
+        let screen = performer_after(5, 10, b"\x1b[3;3H\x1b[1m\x1b8").screen;
+        assert_eq!((screen.row_index, screen.column_index), (0, 0));
+        assert_eq!(screen.pen.attributes, Attributes::default());
+    }
+
+    #[test]
+    fn origin_mode_positions_are_relative_to_the_scroll_region() {
+        let screen = performer_after(5, 10, b"\x1b[2;4r\x1b[?6h").screen;
+        assert_eq!((screen.row_index, screen.column_index), (1, 0));
+
+        let screen = performer_after(5, 10, b"\x1b[2;4r\x1b[?6h\x1b[2;1H").screen;
+        assert_eq!(screen.row_index, 2);
+
+        // Positions past the scroll region are clamped to it.
+        let screen = performer_after(5, 10, b"\x1b[2;4r\x1b[?6h\x1b[9;1H").screen;
+        assert_eq!(screen.row_index, 3);
+
+        // Origin mode is saved with the cursor.
+        let screen = performer_after(5, 10, b"\x1b[2;4r\x1b[?6h\x1b7\x1b[?6l\x1b8").screen;
+        assert!(screen.modes.origin);
+    }
+
+    #[test]
+    fn default_tab_stops_are_every_8_columns() {
+        let screen = performer_after(2, 20, b"a\tb").screen;
+        assert_eq!(screen.rows[0][8].c, 'b');
+
+        // The last column if there are no more tab stops.
+        let screen = performer_after(2, 20, b"\t\t\t").screen;
+        assert_eq!(screen.column_index, 19);
+    }
+
+    #[test]
+    fn tab_stops_can_be_set_and_cleared() {
+        // Set a tab stop at column 3, and clear the one at column 8.
+        let screen = performer_after(2, 20, b"\x1b[1;4H\x1bH\x1b[1;9H\x1b[g\x1b[1;1H\t\t").screen;
+        assert_eq!(screen.column_index, 16);
+
+        // Clear all tab stops.
+        let screen = performer_after(2, 20, b"\x1b[3g\t").screen;
+        assert_eq!(screen.column_index, 19);
+    }
+
+    #[test]
+    fn forward_and_backward_tabulation() {
+        let screen = performer_after(2, 30, b"\x1b[2I").screen;
+        assert_eq!(screen.column_index, 16);
+
+        let screen = performer_after(2, 30, b"\x1b[1;20H\x1b[2Z").screen;
+        assert_eq!(screen.column_index, 8);
+
+        // The first column if there are no more tab stops.
+        let screen = performer_after(2, 30, b"\x1b[1;20H\x1b[9Z").screen;
+        assert_eq!(screen.column_index, 0);
+    }
+
+    #[test]
+    fn dec_special_graphics_draws_lines() {
+        let performer = performer_after(2, 5, b"\x1b(0lqk\x1b(Bq");
+        assert_eq!(row_text(&performer, 0), "┌─┐q ");
+    }
+
+    #[test]
+    fn shifting_between_character_sets() {
+        // G1 with shift out and back to G0 with shift in.
+        let performer = performer_after(2, 5, b"\x1b)0x\x0ex\x0fx");
+        assert_eq!(row_text(&performer, 0), "x│x  ");
+
+        // G2 and G3 with locking shifts.
+        let performer = performer_after(2, 5, b"\x1b*A\x1b+0\x1bn#\x1bo#");
+        assert_eq!(row_text(&performer, 0), "£#   ");
+    }
+
+    #[test]
+    fn single_shifts_only_apply_to_the_next_character() {
+        let performer = performer_after(2, 5, b"\x1b*0\x1bNqq\x1b+A\x1bO##");
+        assert_eq!(row_text(&performer, 0), "─q£# ");
+    }
+
+    #[test]
+    fn device_status_and_cursor_position_reports() {
+        let performer = performer_after(5, 10, b"\x1b[5n");
+        assert_eq!(performer.responses, b"\x1b[0n");
+
+        let performer = performer_after(5, 10, b"\x1b[3;4H\x1b[6n");
+        assert_eq!(performer.responses, b"\x1b[3;4R");
+
+        let performer = performer_after(5, 10, b"\x1b[3;4H\x1b[?6n");
+        assert_eq!(performer.responses, b"\x1b[?3;4R");
+
+        // In origin mode, the row is reported relative to the scroll region.
+        let performer = performer_after(5, 10, b"\x1b[2;4r\x1b[?6h\x1b[2;1H\x1b[6n");
+        assert_eq!(performer.responses, b"\x1b[2;1R");
+    }
+
+    #[test]
+    fn responses_are_collected_in_order() {
+        let performer = performer_after(5, 10, b"\x1b[5n\x1b[6n");
+        assert_eq!(performer.responses, b"\x1b[0n\x1b[1;1R");
+    }
+
+    #[test]
+    fn device_attributes() {
+        let performer = performer_after(5, 10, b"\x1b[c");
+        assert_eq!(performer.responses, b"\x1b[?62;22c");
+
+        let performer = performer_after(5, 10, b"\x1b[0c");
+        assert_eq!(performer.responses, b"\x1b[?62;22c");
+
+        let performer = performer_after(5, 10, b"\x1b[>c");
+        let expected = format!("\x1b[>1;{};0c", version_number());
+        assert_eq!(performer.responses, expected.as_bytes());
+
+        let performer = performer_after(5, 10, b"\x1b[=c");
+        assert_eq!(performer.responses, b"\x1bP!|00000000\x1b\\");
+    }
+
+    #[test]
+    fn version_number_has_two_digits_per_part() {
+        let mut parts = env!("CARGO_PKG_VERSION")
+            .split('.')
+            .map(|part| part.parse::<u32>().unwrap());
+        let (major, minor, patch) = (
+            parts.next().unwrap(),
+            parts.next().unwrap(),
+            parts.next().unwrap(),
+        );
+
+        assert_eq!(version_number(), major * 10000 + minor * 100 + patch);
+    }
+
+    #[test]
+    fn xtversion_reports_the_name_and_version() {
+        let performer = performer_after(5, 10, b"\x1b[>q");
+        let expected = format!("\x1bP>|rustole {}\x1b\\", env!("CARGO_PKG_VERSION"));
+        assert_eq!(performer.responses, expected.as_bytes());
+
+        // Without the ">" marker this is not a version request.
+        let performer = performer_after(5, 10, b"\x1b[q");
+        assert!(performer.responses.is_empty());
+    }
+
+    #[test]
+    fn decrqm_reports_private_modes() {
+        let performer = performer_after(5, 10, b"\x1b[?2004h\x1b[?2004$p\x1b[?1004$p");
+        assert_eq!(performer.responses, b"\x1b[?2004;1$y\x1b[?1004;2$y");
+
+        // Unsupported private modes and all ANSI modes are reported as not recognized.
+        let performer = performer_after(5, 10, b"\x1b[?9999$p\x1b[4$p");
+        assert_eq!(performer.responses, b"\x1b[?9999;0$y\x1b[4;0$y");
+    }
+
+    #[test]
+    fn every_supported_mode_can_be_set_reset_and_reported() {
+        for mode in [1, 6, 7, 25, 47, 66, 1004, 1047, 1049, 2004] {
+            let set = performer_after(5, 10, format!("\x1b[?{mode}l\x1b[?{mode}h").as_bytes());
+            assert_eq!(
+                set.screen.modes.private_mode(mode),
+                Some(true),
+                "mode {mode}"
+            );
+
+            let reset = performer_after(5, 10, format!("\x1b[?{mode}h\x1b[?{mode}l").as_bytes());
+            assert_eq!(
+                reset.screen.modes.private_mode(mode),
+                Some(false),
+                "mode {mode}"
+            );
+        }
+    }
+
+    #[test]
+    fn decrqss_reports_the_graphic_rendition_and_the_scroll_region() {
+        let performer = performer_after(5, 10, b"\x1b[1;38;5;208m\x1bP$qm\x1b\\");
+        assert_eq!(performer.responses, b"\x1bP1$r0;1;38;5;208m\x1b\\");
+
+        let performer = performer_after(5, 10, b"\x1b[2;4r\x1bP$qr\x1b\\");
+        assert_eq!(performer.responses, b"\x1bP1$r2;4r\x1b\\");
+
+        let performer = performer_after(5, 10, b"\x1bP$qx\x1b\\");
+        assert_eq!(performer.responses, b"\x1bP0$r\x1b\\");
+    }
+}
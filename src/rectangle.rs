@@ -0,0 +1,130 @@
+/// A filled rectangle on the screen, with its position and size given in pixels.
+#[derive(Clone, Copy, Debug)]
+pub struct Rectangle {
+    pub position: (f32, f32),
+    pub size: (f32, f32),
+    pub color: [f32; 4],
+}
+
+// Every vertex consists of a position in clip space (2 floats) and a color (4 floats).
+const FLOATS_PER_VERTEX: usize = 6;
+
+/// Draws filled rectangles (for example cell backgrounds), in a similar fashion to the TextBrush of wgpu_text: the
+/// rectangles are queued once per frame and then drawn in a render pass.
+pub struct RectangleBrush {
+    pipeline: wgpu::RenderPipeline,
+    vertex_buffer: wgpu::Buffer,
+    number_of_vertices: u32,
+    width: f32,
+    height: f32,
+}
+
+impl RectangleBrush {
+    pub fn new(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+    ) -> Self {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("shaders/rectangle.wgsl"));
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Rectangle pipeline"),
+            layout: None,
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: (FLOATS_PER_VERTEX * std::mem::size_of::<f32>()) as u64,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x4],
+                }],
+                compilation_options: Default::default(),
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            multiview: None,
+            cache: None,
+        });
+
+        let vertex_buffer = Self::create_vertex_buffer(device, 0);
+
+        RectangleBrush {
+            pipeline,
+            vertex_buffer,
+            number_of_vertices: 0,
+            width: width as f32,
+            height: height as f32,
+        }
+    }
+
+    fn create_vertex_buffer(device: &wgpu::Device, size: u64) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Rectangle vertex buffer"),
+            size,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    pub fn resize_view(&mut self, width: f32, height: f32) {
+        self.width = width;
+        self.height = height;
+    }
+
+    /// Upload the given rectangles to the GPU, replacing the ones queued before.
+    pub fn queue(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, rectangles: &[Rectangle]) {
+        // Pixel coordinates (with the origin at the top left corner) to clip space coordinates.
+        let to_clip_space = |x: f32, y: f32| [x / self.width * 2. - 1., 1. - y / self.height * 2.];
+
+        let mut vertices: Vec<f32> = Vec::with_capacity(rectangles.len() * 6 * FLOATS_PER_VERTEX);
+        for rectangle in rectangles {
+            let (left, top) = rectangle.position;
+            let (right, bottom) = (left + rectangle.size.0, top + rectangle.size.1);
+
+            // Two triangles per rectangle.
+            for (x, y) in [
+                (left, top),
+                (left, bottom),
+                (right, top),
+                (right, top),
+                (left, bottom),
+                (right, bottom),
+            ] {
+                vertices.extend(to_clip_space(x, y));
+                vertices.extend(rectangle.color);
+            }
+        }
+
+        let data: Vec<u8> = vertices
+            .iter()
+            .flat_map(|float| float.to_ne_bytes())
+            .collect();
+
+        if data.len() as u64 > self.vertex_buffer.size() {
+            self.vertex_buffer = Self::create_vertex_buffer(device, data.len() as u64);
+        }
+        queue.write_buffer(&self.vertex_buffer, 0, &data);
+
+        self.number_of_vertices = (vertices.len() / FLOATS_PER_VERTEX) as u32;
+    }
+
+    pub fn draw(&self, render_pass: &mut wgpu::RenderPass) {
+        if self.number_of_vertices != 0 {
+            render_pass.set_pipeline(&self.pipeline);
+            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+            render_pass.draw(0..self.number_of_vertices, 0..1);
+        }
+    }
+}
@@ -1,65 +1,1064 @@
-use wgpu_text::glyph_brush::{Layout, OwnedSection, Section, Text};
+use std::collections::VecDeque;
+use std::ops::{Deref, DerefMut, Range};
 
-/// This is a structure in order to realize rows of lines on our terminal, which we can later manipulate based on incoming control sequences coming from the shell.
+use unicode_width::UnicodeWidthChar;
+
+use crate::color::Color;
+
+/// The graphic rendition attributes of a cell, set by SGR ("CSI ... m") sequences.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Attributes {
+    pub bold: bool,
+    pub dim: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub blink: bool,
+    pub inverse: bool,
+    pub hidden: bool,
+    pub strikethrough: bool,
+}
+
+/// A single cell of the terminal grid: the character shown in it and the attributes it is drawn with.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cell {
+    pub c: char,
+    // Zero-width characters (like combining accents) and joined characters (like the members of ZWJ emoji sequences)
+    // that belong to the same grapheme cluster as c, and are drawn together with it in this cell.
+    pub combining: String,
+    pub fg: Color,
+    pub bg: Color,
+    pub attributes: Attributes,
+    pub wide: bool, // The character is double-width, and the next cell is its spacer.
+    pub spacer: bool, // This cell is the right half of the double-width character in the previous cell.
+}
+
+impl Cell {
+    /// The whole grapheme cluster shown in this cell.
+    pub fn grapheme(&self) -> String {
+        let mut grapheme = String::from(self.c);
+        grapheme.push_str(&self.combining);
+        grapheme
+    }
+
+    /// Whether the given character should be drawn in this cell as part of its grapheme cluster, instead of getting a
+    /// cell of its own.
+    fn extends_grapheme(&self, c: char) -> bool {
+        let is_regional_indicator = |c: char| ('\u{1F1E6}'..='\u{1F1FF}').contains(&c);
+
+        // Zero-width characters (combining marks, variation selectors, zero width joiners).
+        c.width() == Some(0)
+            // The character following a zero width joiner.
+            || self.combining.ends_with('\u{200D}')
+            // Emoji skin tone modifiers.
+            || ('\u{1F3FB}'..='\u{1F3FF}').contains(&c)
+            // The second regional indicator of a flag.
+            || (is_regional_indicator(c) && is_regional_indicator(self.c) && self.combining.is_empty())
+    }
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Cell {
+            c: ' ',
+            combining: String::new(),
+            fg: Color::DefaultForeground,
+            bg: Color::DefaultBackground,
+            attributes: Attributes::default(),
+            wide: false,
+            spacer: false,
+        }
+    }
+}
+
+/// A row of the terminal grid.
+#[derive(Clone, Debug)]
+pub struct Row {
+    pub cells: Vec<Cell>,
+    // Whether the line continues on the next row because it was wrapped, rather than ending with a real newline.
+    pub wrapped: bool,
+}
+
+impl Row {
+    pub fn new(num_cols: usize) -> Self {
+        Row {
+            cells: vec![Cell::default(); num_cols],
+            wrapped: false,
+        }
+    }
+
+    /// Fill the whole row with the given cell, this also makes it an unwrapped row again.
+    pub fn reset(&mut self, cell: &Cell) {
+        self.cells.fill(cell.clone());
+        self.wrapped = false;
+    }
+}
+
+impl Deref for Row {
+    type Target = Vec<Cell>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.cells
+    }
+}
+
+impl DerefMut for Row {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.cells
+    }
+}
+
+/// Terminal modes that can be switched on and off by the application running in the shell.
+#[derive(Clone, Copy, Debug)]
+pub struct Modes {
+    pub show_cursor: bool,             // DECTCEM.
+    pub auto_wrap: bool,               // DECAWM.
+    pub alternate_screen: bool, // Whether the alternate screen buffer is active (modes 47, 1047 and 1049).
+    pub origin: bool,           // DECOM, cursor positions are relative to the scroll region.
+    pub application_cursor_keys: bool, // DECCKM, the arrow keys send SS3 instead of CSI sequences.
+    pub application_keypad: bool, // DECKPAM/DECNKM, the numpad keys send SS3 sequences instead of their characters.
+    pub bracketed_paste: bool,    // Pasted text is wrapped in "ESC [ 200 ~" and "ESC [ 201 ~".
+    pub focus_reporting: bool, // "ESC [ I" and "ESC [ O" are sent when the window gains or loses focus.
+}
+
+impl Modes {
+    /// The flag of the DEC private mode with the given number, or None if it is not supported. This is the list of the
+    /// modes we support, both for setting them and for reporting their state.
+    pub fn private_mode_mut(&mut self, mode: u16) -> Option<&mut bool> {
+        let flag = match mode {
+            1 => &mut self.application_cursor_keys,
+            6 => &mut self.origin,
+            7 => &mut self.auto_wrap,
+            25 => &mut self.show_cursor,
+            47 | 1047 | 1049 => &mut self.alternate_screen,
+            66 => &mut self.application_keypad,
+            1004 => &mut self.focus_reporting,
+            2004 => &mut self.bracketed_paste,
+            _ => return None,
+        };
+
+        Some(flag)
+    }
+
+    /// The state of the DEC private mode with the given number, or None if it is not supported.
+    pub fn private_mode(&self, mode: u16) -> Option<bool> {
+        let mut modes = *self;
+        modes.private_mode_mut(mode).map(|flag| *flag)
+    }
+}
+
+impl Default for Modes {
+    fn default() -> Self {
+        Modes {
+            show_cursor: true,
+            auto_wrap: true,
+            alternate_screen: false,
+            origin: false,
+            application_cursor_keys: false,
+            application_keypad: false,
+            bracketed_paste: false,
+            focus_reporting: false,
+        }
+    }
+}
+
+/// The character sets that can be designated to G0-G3.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Charset {
+    #[default]
+    Ascii,
+    Uk,                 // Like ASCII, but with "£" instead of "#".
+    DecSpecialGraphics, // Line drawing characters.
+}
+
+/// The cursor state saved by save_cursor(), which can be brought back by restore_cursor().
+#[derive(Clone, Debug)]
+pub struct SavedCursor {
+    pub row_index: usize,
+    pub column_index: usize,
+    pub wrap_pending: bool,
+    pub pen: Cell,
+    pub origin: bool,
+    pub charsets: [Charset; 4],
+    pub active_charset: usize,
+}
+
+/// This is the state of our terminal, without anything to do with rendering. The Performer manipulates it based on
+/// incoming control sequences coming from the shell, and the renderer only reads it in order to draw it on the window.
 pub struct Screen {
-    //  Each Section represents a glyph on the screen. Therefore it is defined as a vector of vectors, representing the 2D screen.
-    pub glyphs: Vec<Vec<OwnedSection>>,
-    pub font_size: f32,
-    pub char_width: f32,
+    // The 2D grid of cells, indexed as rows[row][column].
+    pub rows: Vec<Row>,
+    // The grid that is not being shown at the moment. This is the alternate screen while the normal screen is active,
+    // and the normal screen while the alternate screen is active.
+    pub inactive_rows: Vec<Row>,
+    // Cursor position.
     pub row_index: usize,
     pub column_index: usize,
-    pub screen_width: u32,
-    pub screen_height: u32,
+    // Set when a character has been printed in the last column. The cursor stays there, and the line is only wrapped
+    // when the next character is printed, so that a line filling exactly the whole row does not leave an empty row.
+    pub wrap_pending: bool,
+    // The attributes that newly printed characters get (only the attributes of this cell are used, not the character).
+    pub pen: Cell,
+    pub modes: Modes,
+    // The character sets designated to G0-G3, and the index of the one that is used for printing.
+    pub charsets: [Charset; 4],
+    pub active_charset: usize,
+    // The index of the character set to use for only the next printed character (set by SS2 and SS3).
+    pub single_shift: Option<usize>,
+    // The saved cursors of the normal and the alternate screen (in this order), each screen has its own.
+    pub saved_cursors: [Option<SavedCursor>; 2],
+    // The scroll region (set by DECSTBM), the rows from scroll_top to scroll_bottom (inclusive) are the only ones that
+    // are moved when scrolling.
+    pub scroll_top: usize,
+    pub scroll_bottom: usize,
+    // Whether there is a tab stop at the column with the same index.
+    pub tab_stops: Vec<bool>,
+    // The lines that were scrolled off the top of the screen, the most recent one is at the back.
+    pub scrollback: VecDeque<Row>,
+    pub scrollback_size: usize, // The maximum number of lines kept in the scrollback.
+    // How many lines the display is scrolled back into the scrollback, 0 means that we are looking at the bottom.
+    pub display_offset: usize,
 }
 
 impl Screen {
-    pub fn new(
-        font_size: f32,
-        char_width: f32,
-        screen_width: u32,
-        screen_height: u32,
-        offset_from_left: f32,
-        offset_from_top: f32,
-    ) -> Self {
-        let mut glyphs: Vec<Vec<OwnedSection>> = vec![];
-        let num_rows = screen_height / font_size as u32;
-        let num_cols = screen_width / char_width as u32;
-
-        // TODO: Set the line properties correctly.
-        for row_idx in 0..num_rows {
-            let screen_pos_y =
-                (screen_height as f32 * offset_from_top) + (row_idx as f32 * font_size);
-
-            let mut glyphs_in_line = vec![];
-
-            for col_idx in 0..num_cols {
-                let screen_pos_x = offset_from_left + (char_width * col_idx as f32);
-
-                let section = Section::default()
-                    .add_text(
-                        Text::new("")
-                            .with_scale(font_size)
-                            .with_color([0.6, 0.6, 0.5, 1.0]),
-                    )
-                    .with_bounds((screen_width as f32 * 0.95, screen_height as f32))
-                    .with_layout(Layout::default_single_line())
-                    .with_screen_position((screen_pos_x, screen_pos_y))
-                    .to_owned();
-
-                glyphs_in_line.push(section);
+    pub fn new(num_rows: usize, num_cols: usize, scrollback_size: usize) -> Self {
+        Screen {
+            rows: vec![Row::new(num_cols); num_rows],
+            inactive_rows: vec![Row::new(num_cols); num_rows],
+            row_index: 0,
+            column_index: 0,
+            wrap_pending: false,
+            pen: Cell::default(),
+            modes: Modes::default(),
+            charsets: [Charset::default(); 4],
+            active_charset: 0,
+            single_shift: None,
+            saved_cursors: [None, None],
+            scroll_top: 0,
+            scroll_bottom: num_rows - 1,
+            tab_stops: (0..num_cols).map(|col| col % 8 == 0).collect(),
+            scrollback: VecDeque::new(),
+            scrollback_size,
+            display_offset: 0,
+        }
+    }
+
+    /// Put a character at the cursor position using the current pen and advance the cursor. If the cursor is at the last
+    /// column, it stays there and the line is wrapped when the next character is printed (if auto-wrap is enabled).
+    /// Double-width characters take up two cells, and are wrapped to the next line if only one cell is left.
+    pub fn print(&mut self, c: char) {
+        if let Some(previous) = self.previous_cell_mut() {
+            if previous.extends_grapheme(c) {
+                previous.combining.push(c);
+                return;
             }
+        }
+
+        let width = c.width().unwrap_or(1).clamp(1, 2).min(self.num_cols());
+        let wide = width == 2;
 
-            glyphs.push(glyphs_in_line);
+        if self.wrap_pending && self.modes.auto_wrap
+            || (wide && self.modes.auto_wrap && self.column_index + 1 == self.num_cols())
+        {
+            self.rows[self.row_index].wrapped = true;
+            self.carriage_return();
+            self.linefeed();
         }
 
-        Screen {
-            glyphs,
-            font_size,
-            char_width,
-            row_index: 0,
-            column_index: 0,
-            screen_width,
-            screen_height,
+        let col = self.column_index.min(self.num_cols() - width);
+        let row = &mut self.rows[self.row_index];
+        row[col] = Cell {
+            c,
+            wide,
+            ..self.pen.clone()
+        };
+        if wide {
+            row[col + 1] = Cell {
+                c: ' ',
+                spacer: true,
+                ..self.pen.clone()
+            };
+        }
+        self.repair_wide_characters(self.row_index, col);
+        self.repair_wide_characters(self.row_index, col + width);
+
+        if col + width < self.num_cols() {
+            self.column_index = col + width;
+            self.wrap_pending = false;
+        } else {
+            self.column_index = self.num_cols() - 1;
+            self.wrap_pending = self.modes.auto_wrap;
+        }
+    }
+
+    /// The cell of the character that was printed last on the current line (the one left of the cursor, or under the
+    /// cursor if a wrap is pending), if there is one.
+    fn previous_cell_mut(&mut self) -> Option<&mut Cell> {
+        let mut col = if self.wrap_pending {
+            self.column_index
+        } else {
+            self.column_index.checked_sub(1)?
+        };
+
+        let row = &mut self.rows[self.row_index];
+        if row[col].spacer && col > 0 {
+            col -= 1;
+        }
+
+        Some(&mut row[col])
+    }
+
+    /// Make sure that a double-width character is not split at the boundary before the given column, by erasing its
+    /// remaining half. This is needed when only one of the halves has been overwritten, erased or moved.
+    fn repair_wide_characters(&mut self, row: usize, col: usize) {
+        let blank = self.blank_cell();
+        let row = &mut self.rows[row];
+
+        let left_is_wide = col > 0 && row[col - 1].wide;
+        let right_is_spacer = col < row.len() && row[col].spacer;
+
+        if left_is_wide && !right_is_spacer {
+            row[col - 1] = blank.clone();
+        }
+        if right_is_spacer && !left_is_wide {
+            row[col] = blank;
+        }
+    }
+
+    /// Go down to the next row, scrolling the screen up if we are at the bottom of the scroll region.
+    pub fn linefeed(&mut self) {
+        self.index();
+    }
+
+    pub fn carriage_return(&mut self) {
+        self.column_index = 0;
+        self.wrap_pending = false;
+    }
+
+    pub fn backspace(&mut self) {
+        self.move_cursor_left(1);
+    }
+
+    pub fn num_rows(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn num_cols(&self) -> usize {
+        self.rows[0].len()
+    }
+
+    /// Move the cursor to the given position, clamped to the grid.
+    pub fn move_cursor_to(&mut self, row: usize, col: usize) {
+        self.row_index = row.min(self.num_rows() - 1);
+        self.column_index = col.min(self.num_cols() - 1);
+        self.wrap_pending = false;
+    }
+
+    /// Move the cursor to the given position as the application sees it: relative to the scroll region and clamped to
+    /// it if origin mode is set, and relative to the whole screen otherwise.
+    pub fn set_cursor_position(&mut self, row: usize, col: usize) {
+        let row = if self.modes.origin {
+            (self.scroll_top + row).min(self.scroll_bottom)
+        } else {
+            row
+        };
+
+        self.move_cursor_to(row, col);
+    }
+
+    /// The row of the cursor as the application sees it, see set_cursor_position().
+    pub fn cursor_row(&self) -> usize {
+        if self.modes.origin {
+            self.row_index.saturating_sub(self.scroll_top)
+        } else {
+            self.row_index
+        }
+    }
+
+    /// Move the cursor n rows up, stopping at the top of the scroll region if the cursor is inside of it.
+    pub fn move_cursor_up(&mut self, n: usize) {
+        let top = if self.cursor_in_scroll_region() {
+            self.scroll_top
+        } else {
+            0
+        };
+        let row = self.row_index.saturating_sub(n).max(top);
+        self.move_cursor_to(row, self.column_index);
+    }
+
+    /// Move the cursor n rows down, stopping at the bottom of the scroll region if the cursor is inside of it.
+    pub fn move_cursor_down(&mut self, n: usize) {
+        let bottom = if self.cursor_in_scroll_region() {
+            self.scroll_bottom
+        } else {
+            self.num_rows() - 1
+        };
+        let row = self.row_index.saturating_add(n).min(bottom);
+        self.move_cursor_to(row, self.column_index);
+    }
+
+    pub fn move_cursor_right(&mut self, n: usize) {
+        self.move_cursor_to(self.row_index, self.column_index.saturating_add(n));
+    }
+
+    pub fn move_cursor_left(&mut self, n: usize) {
+        self.move_cursor_to(self.row_index, self.column_index.saturating_sub(n));
+    }
+
+    /// The cell that erased cells are filled with: an empty cell with the current background color.
+    fn blank_cell(&self) -> Cell {
+        Cell {
+            bg: self.pen.bg,
+            ..Cell::default()
+        }
+    }
+
+    /// Erase the given columns of the given row.
+    fn erase(&mut self, row: usize, cols: Range<usize>) {
+        let blank = self.blank_cell();
+        let num_cols = self.num_cols();
+        let cols = cols.start.min(num_cols)..cols.end.min(num_cols);
+
+        if cols == (0..num_cols) {
+            self.rows[row].reset(&blank);
+        } else {
+            self.rows[row][cols.clone()].fill(blank);
+            self.repair_wide_characters(row, cols.start);
+            self.repair_wide_characters(row, cols.end);
+        }
+
+        // The line does not continue on the next row anymore if its end has been erased.
+        if cols.end == num_cols {
+            self.rows[row].wrapped = false;
+        }
+    }
+
+    /// Erase from the cursor to the end of the line.
+    pub fn erase_line_right(&mut self) {
+        self.erase(self.row_index, self.column_index..self.num_cols());
+    }
+
+    /// Erase from the beginning of the line to the cursor (inclusive).
+    pub fn erase_line_left(&mut self) {
+        self.erase(self.row_index, 0..self.column_index + 1);
+    }
+
+    pub fn erase_line(&mut self) {
+        self.erase(self.row_index, 0..self.num_cols());
+    }
+
+    /// Erase from the cursor to the end of the screen.
+    pub fn erase_below(&mut self) {
+        self.erase_line_right();
+        for row in self.row_index + 1..self.num_rows() {
+            self.erase(row, 0..self.num_cols());
+        }
+    }
+
+    /// Erase from the beginning of the screen to the cursor (inclusive).
+    pub fn erase_above(&mut self) {
+        self.erase_line_left();
+        for row in 0..self.row_index {
+            self.erase(row, 0..self.num_cols());
+        }
+    }
+
+    pub fn erase_all(&mut self) {
+        for row in 0..self.num_rows() {
+            self.erase(row, 0..self.num_cols());
+        }
+    }
+
+    /// Erase n characters starting from the cursor, without moving the rest of the line.
+    pub fn erase_characters(&mut self, n: usize) {
+        self.erase(self.row_index, self.column_index..self.column_index + n);
+    }
+
+    /// Insert n blank characters at the cursor, shifting the rest of the line to the right. Characters that are shifted
+    /// past the right edge of the screen are lost.
+    pub fn insert_characters(&mut self, n: usize) {
+        let blank = self.blank_cell();
+        let col = self.column_index.min(self.num_cols() - 1);
+        let line = &mut self.rows[self.row_index][col..];
+        let n = n.min(line.len());
+
+        line.rotate_right(n);
+        line[..n].fill(blank);
+
+        let num_cols = self.num_cols();
+        for boundary in [col, col + n, num_cols] {
+            self.repair_wide_characters(self.row_index, boundary);
+        }
+        self.wrap_pending = false;
+    }
+
+    /// Delete n characters at the cursor, shifting the rest of the line to the left and filling the end with blanks.
+    pub fn delete_characters(&mut self, n: usize) {
+        let blank = self.blank_cell();
+        let col = self.column_index.min(self.num_cols() - 1);
+        let line = &mut self.rows[self.row_index][col..];
+        let n = n.min(line.len());
+
+        line.rotate_left(n);
+        let len = line.len();
+        line[len - n..].fill(blank);
+
+        let num_cols = self.num_cols();
+        for boundary in [col, num_cols - n, num_cols] {
+            self.repair_wide_characters(self.row_index, boundary);
+        }
+        self.rows[self.row_index].wrapped = false;
+        self.wrap_pending = false;
+    }
+
+    /// Move the given rows n rows down, the rows at the bottom are lost and blank rows appear at the top.
+    fn scroll_rows_down(&mut self, rows: Range<usize>, n: usize) {
+        let blank = self.blank_cell();
+        let rows = &mut self.rows[rows];
+        let n = n.min(rows.len());
+
+        rows.rotate_right(n);
+        for row in &mut rows[..n] {
+            row.reset(&blank);
+        }
+    }
+
+    /// Move the given rows n rows up, the rows at the top are lost and blank rows appear at the bottom.
+    fn scroll_rows_up(&mut self, rows: Range<usize>, n: usize) {
+        let blank = self.blank_cell();
+        let rows = &mut self.rows[rows];
+        let n = n.min(rows.len());
+
+        rows.rotate_left(n);
+        let len = rows.len();
+        for row in &mut rows[len - n..] {
+            row.reset(&blank);
+        }
+    }
+
+    /// Insert n blank lines at the cursor, shifting the following lines of the scroll region down. Nothing happens if the
+    /// cursor is outside of the scroll region.
+    pub fn insert_lines(&mut self, n: usize) {
+        if self.cursor_in_scroll_region() {
+            self.scroll_rows_down(self.row_index..self.scroll_bottom + 1, n);
+            self.carriage_return();
+        }
+    }
+
+    /// Delete n lines at the cursor, shifting the following lines of the scroll region up and adding blank lines at the
+    /// bottom of it. Nothing happens if the cursor is outside of the scroll region.
+    pub fn delete_lines(&mut self, n: usize) {
+        if self.cursor_in_scroll_region() {
+            self.scroll_rows_up(self.row_index..self.scroll_bottom + 1, n);
+            self.carriage_return();
         }
     }
+
+    fn cursor_in_scroll_region(&self) -> bool {
+        (self.scroll_top..=self.scroll_bottom).contains(&self.row_index)
+    }
+
+    /// Set the scroll region to the rows from top to bottom (inclusive) and move the cursor to the home position. The
+    /// bottom is clamped to the last row, and regions of less than two rows are ignored.
+    pub fn set_scroll_region(&mut self, top: usize, bottom: usize) {
+        let bottom = bottom.min(self.num_rows() - 1);
+        if top < bottom {
+            self.scroll_top = top;
+            self.scroll_bottom = bottom;
+            self.set_cursor_position(0, 0);
+        }
+    }
+
+    /// Scroll the contents of the scroll region up by n lines.
+    /// Lines scrolled off the top of the screen are saved in the scrollback (only on the normal screen).
+    pub fn scroll_up(&mut self, n: usize) {
+        if self.scroll_top == 0 && !self.modes.alternate_screen {
+            let n = n.min(self.scroll_bottom + 1);
+            for row_idx in 0..n {
+                self.push_to_scrollback(self.rows[row_idx].clone());
+            }
+        }
+
+        self.scroll_rows_up(self.scroll_top..self.scroll_bottom + 1, n);
+    }
+
+    /// Scroll the contents of the scroll region down by n lines.
+    pub fn scroll_down(&mut self, n: usize) {
+        self.scroll_rows_down(self.scroll_top..self.scroll_bottom + 1, n);
+    }
+
+    /// Move the cursor one line down, scrolling the scroll region up if the cursor is at the bottom of it.
+    pub fn index(&mut self) {
+        if self.row_index == self.scroll_bottom {
+            self.scroll_up(1);
+        } else if self.row_index + 1 < self.num_rows() {
+            self.row_index += 1;
+        }
+    }
+
+    /// Move the cursor one line up, scrolling the scroll region down if the cursor is at the top of it.
+    pub fn reverse_index(&mut self) {
+        if self.row_index == self.scroll_top {
+            self.scroll_down(1);
+        } else if self.row_index > 0 {
+            self.row_index -= 1;
+        }
+    }
+
+    fn push_to_scrollback(&mut self, row: Row) {
+        if self.scrollback_size == 0 {
+            return;
+        }
+        if self.scrollback.len() == self.scrollback_size {
+            self.scrollback.pop_front();
+        }
+        self.scrollback.push_back(row);
+
+        // If we are looking at the scrollback, keep looking at the same lines instead of moving with the new output.
+        if self.display_offset > 0 {
+            self.display_offset = (self.display_offset + 1).min(self.scrollback.len());
+        }
+    }
+
+    pub fn clear_scrollback(&mut self) {
+        self.scrollback.clear();
+        self.display_offset = 0;
+    }
+
+    /// Scroll the display n lines back into the scrollback (or forward to the bottom, if n is negative). The scrollback
+    /// belongs to the normal screen, so this does nothing while the alternate screen is active.
+    pub fn scroll_display(&mut self, n: isize) {
+        if self.modes.alternate_screen {
+            return;
+        }
+
+        self.display_offset = self
+            .display_offset
+            .saturating_add_signed(n)
+            .min(self.scrollback.len());
+    }
+
+    pub fn scroll_display_to_bottom(&mut self) {
+        self.display_offset = 0;
+    }
+
+    /// The rows that are currently visible on the display, taking the display offset into account.
+    pub fn visible_rows(&self) -> impl Iterator<Item = &Row> {
+        self.scrollback
+            .iter()
+            .chain(self.rows.iter())
+            .skip(self.scrollback.len() - self.display_offset)
+            .take(self.num_rows())
+    }
+
+    pub fn enter_alternate_screen(&mut self) {
+        if !self.modes.alternate_screen {
+            std::mem::swap(&mut self.rows, &mut self.inactive_rows);
+            self.modes.alternate_screen = true;
+            self.scroll_display_to_bottom();
+        }
+    }
+
+    pub fn leave_alternate_screen(&mut self) {
+        if self.modes.alternate_screen {
+            std::mem::swap(&mut self.rows, &mut self.inactive_rows);
+            self.modes.alternate_screen = false;
+        }
+    }
+
+    /// Save the cursor position, the pen, origin mode and the character sets for the active screen.
+    pub fn save_cursor(&mut self) {
+        self.saved_cursors[self.modes.alternate_screen as usize] = Some(SavedCursor {
+            row_index: self.row_index,
+            column_index: self.column_index,
+            wrap_pending: self.wrap_pending,
+            pen: self.pen.clone(),
+            origin: self.modes.origin,
+            charsets: self.charsets,
+            active_charset: self.active_charset,
+        });
+    }
+
+    /// Restore the cursor saved by save_cursor() on the active screen, or move the cursor to the top left corner and
+    /// reset the pen, origin mode and the character sets if nothing has been saved.
+    pub fn restore_cursor(&mut self) {
+        let saved_cursor = self.saved_cursors[self.modes.alternate_screen as usize]
+            .clone()
+            .unwrap_or(SavedCursor {
+                row_index: 0,
+                column_index: 0,
+                wrap_pending: false,
+                pen: Cell::default(),
+                origin: false,
+                charsets: [Charset::default(); 4],
+                active_charset: 0,
+            });
+
+        self.move_cursor_to(saved_cursor.row_index, saved_cursor.column_index);
+        self.wrap_pending = saved_cursor.wrap_pending;
+        self.pen = saved_cursor.pen;
+        self.modes.origin = saved_cursor.origin;
+        self.charsets = saved_cursor.charsets;
+        self.active_charset = saved_cursor.active_charset;
+    }
+
+    /// Move the cursor forward to the n-th next tab stop, or to the last column if there are not enough tab stops.
+    pub fn tab(&mut self, n: usize) {
+        let mut col = self.column_index;
+        for _ in 0..n {
+            col = (col + 1..self.num_cols())
+                .find(|&col| self.tab_stops[col])
+                .unwrap_or(self.num_cols() - 1);
+        }
+
+        self.move_cursor_to(self.row_index, col);
+    }
+
+    /// Move the cursor back to the n-th previous tab stop, or to the first column if there are not enough tab stops.
+    pub fn back_tab(&mut self, n: usize) {
+        let mut col = self.column_index;
+        for _ in 0..n {
+            col = (0..col).rev().find(|&col| self.tab_stops[col]).unwrap_or(0);
+        }
+
+        self.move_cursor_to(self.row_index, col);
+    }
+
+    pub fn set_tab_stop(&mut self) {
+        self.tab_stops[self.column_index] = true;
+    }
+
+    pub fn clear_tab_stop(&mut self) {
+        self.tab_stops[self.column_index] = false;
+    }
+
+    pub fn clear_all_tab_stops(&mut self) {
+        self.tab_stops.fill(false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn print_str(screen: &mut Screen, text: &str) {
+        for c in text.chars() {
+            screen.print(c);
+        }
+    }
+
+    fn row_text(row: &Row) -> String {
+        row.iter()
+            .filter(|cell| !cell.spacer)
+            .map(Cell::grapheme)
+            .collect()
+    }
+
+    #[test]
+    fn printing_uses_the_pen_and_advances_the_cursor() {
+        let mut screen = Screen::new(3, 5, 10);
+        screen.pen.fg = Color::Indexed(1);
+        print_str(&mut screen, "ab");
+
+        assert_eq!(row_text(&screen.rows[0]), "ab   ");
+        assert_eq!(screen.rows[0][1].fg, Color::Indexed(1));
+        assert_eq!(screen.rows[0][2].fg, Color::DefaultForeground);
+        assert_eq!((screen.row_index, screen.column_index), (0, 2));
+    }
+
+    #[test]
+    fn cursor_movement_stops_at_the_scroll_region_margins() {
+        let mut screen = Screen::new(6, 3, 10);
+        screen.set_scroll_region(1, 4);
+
+        screen.move_cursor_to(3, 0);
+        screen.move_cursor_up(10);
+        assert_eq!(screen.row_index, 1);
+
+        screen.move_cursor_down(10);
+        assert_eq!(screen.row_index, 4);
+
+        // Outside of the region, the cursor can move to the edge of the screen.
+        screen.move_cursor_to(5, 0);
+        screen.move_cursor_up(10);
+        assert_eq!(screen.row_index, 0);
+    }
+
+    #[test]
+    fn insert_and_delete_lines_cancel_the_pending_wrap() {
+        let mut screen = Screen::new(3, 5, 10);
+        print_str(&mut screen, "abcde");
+        screen.insert_lines(1);
+        screen.print('x');
+
+        assert_eq!(row_text(&screen.rows[0]), "x    ");
+        assert_eq!(row_text(&screen.rows[1]), "abcde");
+        assert!(!screen.rows[0].wrapped);
+
+        screen.move_cursor_to(1, 4);
+        screen.print('E');
+        screen.delete_lines(1);
+        screen.print('y');
+
+        assert_eq!(row_text(&screen.rows[1]), "y    ");
+        assert!(!screen.rows[1].wrapped);
+    }
+
+    #[test]
+    fn scrolling_inside_a_region_leaves_the_other_rows_alone() {
+        let mut screen = Screen::new(4, 3, 10);
+        for (row, text) in ["top", "one", "two", "bot"].iter().enumerate() {
+            screen.move_cursor_to(row, 0);
+            print_str(&mut screen, text);
+        }
+
+        screen.set_scroll_region(1, 2);
+        assert_eq!((screen.row_index, screen.column_index), (0, 0));
+
+        screen.move_cursor_to(2, 0);
+        screen.linefeed();
+
+        let rows: Vec<String> = screen.rows.iter().map(row_text).collect();
+        assert_eq!(rows, ["top", "two", "   ", "bot"]);
+        assert_eq!(screen.row_index, 2);
+        // Only lines scrolled off the top of the screen go to the scrollback.
+        assert!(screen.scrollback.is_empty());
+    }
+
+    #[test]
+    fn scroll_region_bottom_is_clamped_to_the_screen() {
+        let mut screen = Screen::new(4, 3, 10);
+        screen.set_scroll_region(1, 998);
+
+        assert_eq!((screen.scroll_top, screen.scroll_bottom), (1, 3));
+
+        // A region of a single row is ignored.
+        screen.set_scroll_region(2, 2);
+        assert_eq!((screen.scroll_top, screen.scroll_bottom), (1, 3));
+    }
+
+    #[test]
+    fn filling_the_last_column_defers_the_wrap() {
+        let mut screen = Screen::new(3, 5, 10);
+        print_str(&mut screen, "abcde");
+
+        assert_eq!((screen.row_index, screen.column_index), (0, 4));
+        assert!(screen.wrap_pending);
+        assert!(!screen.rows[0].wrapped);
+
+        screen.print('f');
+
+        assert_eq!(row_text(&screen.rows[0]), "abcde");
+        assert_eq!(row_text(&screen.rows[1]), "f    ");
+        assert_eq!((screen.row_index, screen.column_index), (1, 1));
+        assert!(screen.rows[0].wrapped);
+    }
+
+    #[test]
+    fn carriage_return_cancels_the_pending_wrap() {
+        let mut screen = Screen::new(3, 5, 10);
+        print_str(&mut screen, "abcde");
+        screen.carriage_return();
+        screen.linefeed();
+        screen.print('f');
+
+        assert_eq!(row_text(&screen.rows[1]), "f    ");
+        assert!(!screen.rows[0].wrapped);
+    }
+
+    #[test]
+    fn without_auto_wrap_the_last_column_is_overwritten() {
+        let mut screen = Screen::new(3, 5, 10);
+        screen.modes.auto_wrap = false;
+        print_str(&mut screen, "abcdefg");
+
+        assert_eq!(row_text(&screen.rows[0]), "abcdg");
+        assert_eq!(screen.row_index, 0);
+        assert!(!screen.wrap_pending);
+    }
+
+    #[test]
+    fn erasing_the_end_of_a_wrapped_row_clears_the_wrapped_flag() {
+        let mut screen = Screen::new(3, 5, 10);
+        print_str(&mut screen, "abcdefg");
+        assert!(screen.rows[0].wrapped);
+
+        screen.move_cursor_to(0, 2);
+        screen.erase_line_right();
+
+        assert_eq!(row_text(&screen.rows[0]), "ab   ");
+        assert!(!screen.rows[0].wrapped);
+
+        print_str(&mut screen, "cdefg");
+        screen.move_cursor_to(0, 1);
+        screen.delete_characters(1);
+
+        assert!(!screen.rows[0].wrapped);
+    }
+
+    #[test]
+    fn lines_scrolled_off_the_top_go_to_the_scrollback() {
+        let mut screen = Screen::new(2, 3, 2);
+        for text in ["l1", "l2", "l3", "l4"] {
+            print_str(&mut screen, text);
+            screen.carriage_return();
+            screen.linefeed();
+        }
+
+        // Only the last 2 lines are kept.
+        let scrollback: Vec<String> = screen.scrollback.iter().map(row_text).collect();
+        assert_eq!(scrollback, ["l2 ", "l3 "]);
+
+        screen.scroll_display(1);
+        let visible: Vec<String> = screen.visible_rows().map(row_text).collect();
+        assert_eq!(visible, ["l3 ", "l4 "]);
+
+        // Scrolling further than the scrollback stops at its top.
+        screen.scroll_display(10);
+        assert_eq!(screen.display_offset, 2);
+
+        screen.scroll_display_to_bottom();
+        let visible: Vec<String> = screen.visible_rows().map(row_text).collect();
+        assert_eq!(visible, ["l4 ", "   "]);
+    }
+
+    #[test]
+    fn new_output_keeps_the_scrolled_back_display_in_place() {
+        let mut screen = Screen::new(2, 3, 10);
+        for text in ["l1", "l2", "l3"] {
+            print_str(&mut screen, text);
+            screen.carriage_return();
+            screen.linefeed();
+        }
+        screen.scroll_display(1);
+        let before: Vec<String> = screen.visible_rows().map(row_text).collect();
+
+        print_str(&mut screen, "l4");
+        screen.carriage_return();
+        screen.linefeed();
+
+        let after: Vec<String> = screen.visible_rows().map(row_text).collect();
+        assert_eq!(before, after);
+        assert_eq!(screen.display_offset, 2);
+    }
+
+    #[test]
+    fn alternate_screen_keeps_the_normal_screen_and_its_own_saved_cursor() {
+        let mut screen = Screen::new(3, 5, 10);
+        print_str(&mut screen, "shell");
+        screen.move_cursor_to(1, 2);
+        screen.save_cursor();
+
+        screen.enter_alternate_screen();
+        screen.erase_all();
+        print_str(&mut screen, "vim");
+        screen.move_cursor_to(2, 0);
+        screen.save_cursor();
+
+        // The alternate screen does not scroll into the scrollback.
+        for _ in 0..5 {
+            screen.linefeed();
+        }
+        assert!(screen.scrollback.is_empty());
+
+        screen.leave_alternate_screen();
+        screen.restore_cursor();
+
+        assert_eq!(row_text(&screen.rows[0]), "shell");
+        assert_eq!((screen.row_index, screen.column_index), (1, 2));
+    }
+
+    #[test]
+    fn wide_characters_take_two_cells() {
+        let mut screen = Screen::new(3, 5, 10);
+        print_str(&mut screen, "a中b");
+
+        let row = &screen.rows[0];
+        assert!(row[1].wide);
+        assert!(row[2].spacer);
+        assert_eq!(row_text(row), "a中b ");
+        assert_eq!(screen.column_index, 4);
+    }
+
+    #[test]
+    fn wide_character_at_the_last_column_wraps_early() {
+        let mut screen = Screen::new(3, 5, 10);
+        print_str(&mut screen, "abcd中");
+
+        assert_eq!(row_text(&screen.rows[0]), "abcd ");
+        assert!(screen.rows[0].wrapped);
+        assert!(screen.rows[1][0].wide);
+        assert!(screen.rows[1][1].spacer);
+        assert_eq!((screen.row_index, screen.column_index), (1, 2));
+    }
+
+    #[test]
+    fn overwriting_half_of_a_wide_character_erases_the_other_half() {
+        let mut screen = Screen::new(3, 5, 10);
+        print_str(&mut screen, "中文");
+
+        // Overwrite the right half of the first character.
+        screen.move_cursor_to(0, 1);
+        screen.print('x');
+
+        let row = &screen.rows[0];
+        assert_eq!(row_text(row), " x文 ");
+        assert!(!row[0].wide);
+        assert!(!row[1].spacer);
+
+        // Overwrite the left half of the second character.
+        screen.move_cursor_to(0, 2);
+        screen.print('y');
+
+        let row = &screen.rows[0];
+        assert_eq!(row_text(row), " xy  ");
+        assert!(!row[3].spacer);
+    }
+
+    #[test]
+    fn deleting_characters_does_not_leave_half_a_wide_character() {
+        let mut screen = Screen::new(3, 5, 10);
+        print_str(&mut screen, "a中b");
+        screen.move_cursor_to(0, 2);
+        screen.delete_characters(1);
+
+        let row = &screen.rows[0];
+        assert_eq!(row_text(row), "a b  ");
+        assert!(row.iter().all(|cell| !cell.wide && !cell.spacer));
+    }
+
+    #[test]
+    fn combining_characters_stay_in_the_previous_cell() {
+        let mut screen = Screen::new(3, 5, 10);
+        print_str(&mut screen, "e\u{301}x");
+
+        assert_eq!(screen.rows[0][0].grapheme(), "e\u{301}");
+        assert_eq!(screen.rows[0][1].c, 'x');
+        assert_eq!(screen.column_index, 2);
+    }
+
+    #[test]
+    fn combining_character_after_the_last_column_joins_it() {
+        let mut screen = Screen::new(3, 5, 10);
+        print_str(&mut screen, "abcde\u{301}");
+
+        assert_eq!(screen.rows[0][4].grapheme(), "e\u{301}");
+        assert_eq!(screen.row_index, 0);
+        assert!(screen.wrap_pending);
+    }
+
+    #[test]
+    fn joined_sequences_and_flags_take_a_single_cell() {
+        let mut screen = Screen::new(3, 10, 10);
+        // Woman, zero width joiner, laptop.
+        print_str(&mut screen, "\u{1F469}\u{200D}\u{1F4BB}");
+        // The regional indicators of a flag.
+        print_str(&mut screen, "\u{1F1EB}\u{1F1F7}");
+
+        let row = &screen.rows[0];
+        assert_eq!(row[0].grapheme(), "\u{1F469}\u{200D}\u{1F4BB}");
+        assert!(row[1].spacer);
+        assert_eq!(row[2].grapheme(), "\u{1F1EB}\u{1F1F7}");
+        assert_eq!(row[3].c, ' ');
+        assert_eq!(screen.column_index, 3);
+    }
 }
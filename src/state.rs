@@ -1,10 +1,13 @@
+use crate::color::{Color, Palette};
 use crate::context::Ctx;
 use crate::performer;
-use crate::screen::Screen;
+use crate::rectangle::{Rectangle, RectangleBrush};
+use crate::screen::{Attributes, Screen};
 use crate::utils;
 
+use arboard::Clipboard;
 use glyph_brush::ab_glyph::{Font, FontRef, ScaleFont};
-use glyph_brush::OwnedSection;
+use glyph_brush::{FontId, OwnedSection};
 use vte::Parser;
 
 use nix::unistd::write;
@@ -12,29 +15,43 @@ use std::os::fd::OwnedFd;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
-use wgpu_text::glyph_brush::{BuiltInLineBreaker, Layout, Section, Text};
+use wgpu_text::glyph_brush::{Layout, Section, Text};
 use wgpu_text::{BrushBuilder, TextBrush};
 
 use winit::application::ApplicationHandler;
 use winit::event::{ElementState, KeyEvent, Modifiers, MouseScrollDelta, WindowEvent};
 use winit::event_loop::ActiveEventLoop;
-use winit::keyboard::{Key, NamedKey};
+use winit::keyboard::{Key, KeyLocation, NamedKey};
 use winit::window::Window;
 
 /// The State struct, which holds the state of the application and acts as the application handler for
 // all the events that can happen to our window that we want to react to.
 pub struct State<'a> {
-    performer: Option<performer::Performer<'a>>,
+    performer: Option<performer::Performer>,
+    pty_fd: &'a OwnedFd, // We will write to this file descriptor, what we write here will be read by the shell on the other side.
     parser: Parser,
     modifiers: Modifiers, // These are keyboard modifiers (for example to check if we are pressing Ctrl at the moment).
-    background_color: [f64; 4],
+    palette: Palette,
+
+    // Rendering related state, the terminal state itself lives in the Screen of the performer.
+    window: Option<Arc<Window>>,
+    fonts: [&'a [u8]; 4], // Regular, bold, italic and bold italic fonts.
+    brush: Option<TextBrush<FontRef<'a>>>,
+    rectangle_brush: Option<RectangleBrush>,
+    font_size: f32,
+    char_width: f32,
+    text_offset_from_left: f32,
+    text_offset_from_top_as_percentage: f32,
+    blink_time: Instant, // Blinking text is visible during the first half of every second since this instant.
+    scroll_remainder: f32, // The part of a line that touchpad scrolling has moved, but that has not been scrolled yet.
 
     target_framerate: Duration,
     delta_time: Instant,
     fps_update_time: Instant,
     fps: i32,
 
-    ctx: Option<Ctx>, // wgpu context.
+    ctx: Option<Ctx>,             // wgpu context.
+    clipboard: Option<Clipboard>, // Created when it is first needed.
 }
 
 impl<'a> ApplicationHandler<utils::SomethingInFd> for State<'a> {
@@ -51,41 +68,28 @@ impl<'a> ApplicationHandler<utils::SomethingInFd> for State<'a> {
         let device = &ctx.device;
         let config = &ctx.config;
 
-        let performer_mut = self.performer.as_mut().unwrap();
-
-        let font_slice = performer_mut.font.as_slice();
-
-        let brush: Option<TextBrush<FontRef<'a>>> =
-            Some(BrushBuilder::using_font_bytes(font_slice).unwrap().build(
-                device,
-                config.width,
-                config.height,
-                config.format,
-            ));
-
-        // Push the initial cursor to the cursor_string.
-
-        let cursor_section = Some(
-            Section::default()
-                .add_text(
-                    Text::new("█")
-                        .with_scale(performer_mut.font_size)
-                        .with_color([0.6, 0.6, 0.5, 0.5]),
-                )
-                .with_bounds((config.width as f32 * 0.95, config.height as f32))
-                .with_layout(Layout::default().line_breaker(BuiltInLineBreaker::AnyCharLineBreaker))
-                .with_screen_position((
-                    performer_mut.text_offset_from_left,
-                    performer_mut.text_offset_from_top_as_percentage / 2.,
-                ))
-                .to_owned(),
-        );
-
-        let window = Some(window);
-
-        performer_mut.brush = brush;
-        performer_mut.window = window;
-        performer_mut.cursor_section = cursor_section;
+        // The order of the fonts here defines the font ids, see font_id().
+        let fonts = self
+            .fonts
+            .iter()
+            .map(|font| FontRef::try_from_slice(font).unwrap())
+            .collect();
+
+        let brush: Option<TextBrush<FontRef<'a>>> = Some(BrushBuilder::using_fonts(fonts).build(
+            device,
+            config.width,
+            config.height,
+            config.format,
+        ));
+
+        self.brush = brush;
+        self.rectangle_brush = Some(RectangleBrush::new(
+            device,
+            config.width,
+            config.height,
+            config.format,
+        ));
+        self.window = Some(window);
     }
 
     fn window_event(
@@ -109,22 +113,15 @@ impl<'a> ApplicationHandler<utils::SomethingInFd> for State<'a> {
 
                 surface.configure(device, config);
 
-                let performer_mut = self.performer.as_mut().unwrap();
-
-                performer_mut.cursor_section.as_mut().unwrap().bounds =
-                    (config.width as f32 * 0.95, config.height as _);
-                performer_mut
-                    .cursor_section
-                    .as_mut()
-                    .unwrap()
-                    .screen_position
-                    .1 = config.height as f32 * performer_mut.text_offset_from_top_as_percentage;
-
-                performer_mut.brush.as_mut().unwrap().resize_view(
+                self.brush.as_mut().unwrap().resize_view(
                     config.width as f32,
                     config.height as f32,
                     queue,
                 );
+                self.rectangle_brush
+                    .as_mut()
+                    .unwrap()
+                    .resize_view(config.width as f32, config.height as f32);
             }
 
             WindowEvent::CloseRequested => event_loop.exit(),
@@ -133,22 +130,82 @@ impl<'a> ApplicationHandler<utils::SomethingInFd> for State<'a> {
                 event:
                     KeyEvent {
                         logical_key,
+                        location,
                         state: ElementState::Pressed,
                         ..
                     },
                 ..
             } => {
+                // Paste from the clipboard with Ctrl+Shift+V.
+                let modifiers = self.modifiers.state();
+                if modifiers.control_key()
+                    && modifiers.shift_key()
+                    && matches!(&logical_key, Key::Character(c) if c.eq_ignore_ascii_case("v"))
+                {
+                    self.paste();
+                    return;
+                }
+
                 let performer_mut = self.performer.as_mut().unwrap();
+                let shift = self.modifiers.state().shift_key();
+                // In application cursor keys mode (DECCKM) the arrow keys are sent as SS3 sequences.
+                let application_cursor_keys = performer_mut.screen.modes.application_cursor_keys;
+
+                // Go back to the bottom of the scrollback on any key that is sent to the shell.
+                match logical_key {
+                    Key::Named(NamedKey::PageUp | NamedKey::PageDown) if shift => (),
+                    Key::Named(
+                        NamedKey::Shift | NamedKey::Control | NamedKey::Alt | NamedKey::Super,
+                    ) => (),
+                    _ => performer_mut.screen.scroll_display_to_bottom(),
+                }
+
+                // In application keypad mode the numpad keys are sent as SS3 sequences.
+                if location == KeyLocation::Numpad && performer_mut.screen.modes.application_keypad
+                {
+                    if let Some(sequence) = Self::application_keypad_sequence(&logical_key) {
+                        match write(self.pty_fd, sequence) {
+                            Ok(_) => (),
+                            Err(e) => {
+                                println!("There has been an error writing to the master pty: {e}")
+                            }
+                        }
+                        return;
+                    }
+                }
+
                 match logical_key {
                     Key::Named(k) => match k {
-                        NamedKey::Escape => match write(performer_mut.pty_fd, b"\x1b") {
+                        NamedKey::PageUp if shift => {
+                            // Scroll one page back into the scrollback.
+                            let page = performer_mut.screen.num_rows() - 1;
+                            performer_mut.screen.scroll_display(page as isize);
+                        }
+                        NamedKey::PageDown if shift => {
+                            // Scroll one page forward.
+                            let page = performer_mut.screen.num_rows() - 1;
+                            performer_mut.screen.scroll_display(-(page as isize));
+                        }
+                        NamedKey::PageUp => match write(self.pty_fd, b"\x1b[5~") {
+                            Ok(_) => (),
+                            Err(e) => {
+                                println!("There has been an error writing to the master pty: {e}")
+                            }
+                        },
+                        NamedKey::PageDown => match write(self.pty_fd, b"\x1b[6~") {
+                            Ok(_) => (),
+                            Err(e) => {
+                                println!("There has been an error writing to the master pty: {e}")
+                            }
+                        },
+                        NamedKey::Escape => match write(self.pty_fd, b"\x1b") {
                             Ok(_) => (),
                             Err(e) => println!(
                                 "There has been an error writing ESC to the master pty: {e}"
                             ),
                         },
                         NamedKey::Delete => {
-                            match write(performer_mut.pty_fd, b"\x1b[3~") {
+                            match write(self.pty_fd, b"\x1b[3~") {
                                 // ESC [ 3 ~
                                 Ok(_) => (),
                                 Err(e) => println!("Error writing forward-delete to pty: {e}"),
@@ -156,7 +213,7 @@ impl<'a> ApplicationHandler<utils::SomethingInFd> for State<'a> {
                         }
                         NamedKey::Enter => {
                             // Send the carriage return character to the master pty.
-                            match write(performer_mut.pty_fd, b"\r") {
+                            match write(self.pty_fd, b"\r") {
                                 Ok(_) => (),
                                 Err(e) => println!(
                                     "There has been an error writing to the master pty: {e}"
@@ -165,7 +222,7 @@ impl<'a> ApplicationHandler<utils::SomethingInFd> for State<'a> {
                         }
                         NamedKey::Backspace => {
                             // Send the backspace character to the master pty.
-                            match write(performer_mut.pty_fd, b"\x7f") {
+                            match write(self.pty_fd, b"\x7f") {
                                 Ok(_) => (),
                                 Err(e) => println!(
                                     "There has been an error writing to the master pty: {e}",
@@ -174,7 +231,7 @@ impl<'a> ApplicationHandler<utils::SomethingInFd> for State<'a> {
                         }
                         NamedKey::Space => {
                             // Send the space character to the master pty.
-                            match write(performer_mut.pty_fd, b" ") {
+                            match write(self.pty_fd, b" ") {
                                 Ok(_) => (),
                                 Err(e) => println!(
                                     "There has been an error writing to the master pty: {e}"
@@ -184,7 +241,12 @@ impl<'a> ApplicationHandler<utils::SomethingInFd> for State<'a> {
 
                         NamedKey::ArrowLeft => {
                             // Send the arrow left escape sequence to the master pty.
-                            match write(performer_mut.pty_fd, b"\x1b[D") {
+                            let sequence: &[u8] = if application_cursor_keys {
+                                b"\x1bOD"
+                            } else {
+                                b"\x1b[D"
+                            };
+                            match write(self.pty_fd, sequence) {
                                 Ok(_) => (),
                                 Err(e) => println!(
                                     "There has been an error writing to the master pty: {e}"
@@ -194,7 +256,12 @@ impl<'a> ApplicationHandler<utils::SomethingInFd> for State<'a> {
 
                         NamedKey::ArrowRight => {
                             // Send the arrow right escape sequence to the master pty.
-                            match write(performer_mut.pty_fd, b"\x1b[C") {
+                            let sequence: &[u8] = if application_cursor_keys {
+                                b"\x1bOC"
+                            } else {
+                                b"\x1b[C"
+                            };
+                            match write(self.pty_fd, sequence) {
                                 Ok(_) => (),
                                 Err(e) => println!(
                                     "There has been an error writing to the master pty: {e}"
@@ -203,7 +270,12 @@ impl<'a> ApplicationHandler<utils::SomethingInFd> for State<'a> {
                         }
                         NamedKey::ArrowUp => {
                             // Send the arrow up escape sequence to the master pty.
-                            match write(performer_mut.pty_fd, b"\x1b[A") {
+                            let sequence: &[u8] = if application_cursor_keys {
+                                b"\x1bOA"
+                            } else {
+                                b"\x1b[A"
+                            };
+                            match write(self.pty_fd, sequence) {
                                 Ok(_) => (),
                                 Err(e) => println!(
                                     "There has been an error writing to the master pty: {e}"
@@ -212,7 +284,12 @@ impl<'a> ApplicationHandler<utils::SomethingInFd> for State<'a> {
                         }
                         NamedKey::ArrowDown => {
                             // Send the arrow down escape sequence to the master pty.
-                            match write(performer_mut.pty_fd, b"\x1b[B") {
+                            let sequence: &[u8] = if application_cursor_keys {
+                                b"\x1bOB"
+                            } else {
+                                b"\x1b[B"
+                            };
+                            match write(self.pty_fd, sequence) {
                                 Ok(_) => (),
                                 Err(e) => println!(
                                     "There has been an error writing to the master pty: {e}"
@@ -221,7 +298,7 @@ impl<'a> ApplicationHandler<utils::SomethingInFd> for State<'a> {
                         }
                         NamedKey::Tab => {
                             // Send the tab character to the master pty.
-                            match write(performer_mut.pty_fd, b"\t") {
+                            match write(self.pty_fd, b"\t") {
                                 Ok(_) => (),
                                 Err(e) => println!(
                                     "There has been an error writing to the master pty: {e}"
@@ -241,7 +318,7 @@ impl<'a> ApplicationHandler<utils::SomethingInFd> for State<'a> {
                             c.as_bytes()[0]
                         };
 
-                        match write(performer_mut.pty_fd, &[byte_to_send]) {
+                        match write(self.pty_fd, &[byte_to_send]) {
                             Ok(_) => (),
                             Err(e) => {
                                 println!("There has been an error writing to the master pty: {e}")
@@ -253,46 +330,53 @@ impl<'a> ApplicationHandler<utils::SomethingInFd> for State<'a> {
                 }
             }
 
-            WindowEvent::MouseWheel {
-                delta: MouseScrollDelta::LineDelta(_, y),
-                ..
-            } => {
-                let performer_mut = self.performer.as_mut().unwrap();
-
-                // Increase/decrease font size.
-                let mut size = performer_mut.font_size;
-                if y > 0.0 {
-                    size += (size / 4.0).max(2.0)
+            WindowEvent::MouseWheel { delta, .. } => {
+                if self.modifiers.state().control_key() {
+                    // Increase/decrease font size, only on wheel steps since touchpads send a lot of small events.
+                    if let MouseScrollDelta::LineDelta(_, y) = delta {
+                        if y != 0.0 {
+                            let mut size = self.font_size;
+                            if y > 0.0 {
+                                size += (size / 4.0).max(2.0)
+                            } else {
+                                size *= 4.0 / 5.0
+                            };
+                            self.font_size = (size.clamp(3.0, 25000.0) * 2.0).round() / 2.0;
+                            self.char_width = Self::char_width(self.fonts[0], self.font_size);
+                        }
+                    }
                 } else {
-                    size *= 4.0 / 5.0
-                };
-                performer_mut.font_size = (size.clamp(3.0, 25000.0) * 2.0).round() / 2.0;
+                    // Wheel steps scroll 3 lines at a time, touchpads scroll as many pixels as they moved.
+                    let lines = match delta {
+                        MouseScrollDelta::LineDelta(_, y) => y * 3.,
+                        MouseScrollDelta::PixelDelta(position) => {
+                            position.y as f32 / self.font_size
+                        }
+                    };
 
-                performer_mut.cursor_section.as_mut().unwrap().text[0].scale =
-                    performer_mut.font_size.into();
+                    // Scroll through the scrollback, keeping the partial line for the next event.
+                    self.scroll_remainder += lines;
+                    let whole_lines = self.scroll_remainder.trunc();
+                    self.scroll_remainder -= whole_lines;
+
+                    let screen = &mut self.performer.as_mut().unwrap().screen;
+                    screen.scroll_display(whole_lines as isize);
+                }
             }
 
             WindowEvent::RedrawRequested => {
-                let performer = self.performer.as_mut().unwrap();
+                let (rectangles, sections) = self.screen_layout();
 
-                let brush = performer.brush.as_mut().unwrap();
+                let brush = self.brush.as_mut().unwrap();
+                let rectangle_brush = self.rectangle_brush.as_mut().unwrap();
                 let ctx = self.ctx.as_ref().unwrap();
                 let queue = &ctx.queue;
                 let device = &ctx.device;
                 let config = &ctx.config;
                 let surface = &ctx.surface;
-                let cursor_section = performer.cursor_section.as_ref().unwrap();
-
-                // NOTE: Section order in the brush queue should be [text_section, cursor_section], once cursor_section is implemented as the cursor, so that it stays on top of the text section.
-
-                let mut screen_section_refs: Vec<&OwnedSection> = performer
-                    .screen
-                    .glyphs
-                    .iter()
-                    .flat_map(|row| row.iter())
-                    .collect();
-                screen_section_refs.push(cursor_section);
-                match brush.queue(device, queue, screen_section_refs) {
+
+                rectangle_brush.queue(device, queue, &rectangles);
+                match brush.queue(device, queue, &sections) {
                     Ok(_) => (),
                     Err(err) => panic!("{err}"),
                 }
@@ -325,11 +409,12 @@ impl<'a> ApplicationHandler<utils::SomethingInFd> for State<'a> {
                             view: &view,
                             resolve_target: None,
                             ops: wgpu::Operations {
-                                load: wgpu::LoadOp::Clear(wgpu::Color { // This represents our background.
-                                    r: self.background_color[0],
-                                    g: self.background_color[1],
-                                    b: self.background_color[2],
-                                    a: self.background_color[3],
+                                load: wgpu::LoadOp::Clear(wgpu::Color {
+                                    // This represents our background.
+                                    r: self.palette.background[0] as f64,
+                                    g: self.palette.background[1] as f64,
+                                    b: self.palette.background[2] as f64,
+                                    a: self.palette.background[3] as f64,
                                 }),
                                 store: wgpu::StoreOp::Store,
                             },
@@ -339,6 +424,8 @@ impl<'a> ApplicationHandler<utils::SomethingInFd> for State<'a> {
                         occlusion_query_set: None,
                     });
 
+                    // The cell backgrounds have to be drawn before the text, so that they stay behind it.
+                    rectangle_brush.draw(&mut render_pass);
                     brush.draw(&mut render_pass);
                 }
 
@@ -346,6 +433,20 @@ impl<'a> ApplicationHandler<utils::SomethingInFd> for State<'a> {
                 frame.present();
             }
 
+            WindowEvent::Focused(focused) => {
+                // Let the application know that the window gained or lost focus, if it asked for it.
+                let performer_mut = self.performer.as_mut().unwrap();
+                if performer_mut.screen.modes.focus_reporting {
+                    let sequence: &[u8] = if focused { b"\x1b[I" } else { b"\x1b[O" };
+                    match write(self.pty_fd, sequence) {
+                        Ok(_) => (),
+                        Err(e) => {
+                            println!("There has been an error writing to the master pty: {e}")
+                        }
+                    }
+                }
+            }
+
             WindowEvent::ModifiersChanged(modifiers) => {
                 self.modifiers = modifiers;
             }
@@ -356,16 +457,13 @@ impl<'a> ApplicationHandler<utils::SomethingInFd> for State<'a> {
     fn new_events(&mut self, _event_loop: &ActiveEventLoop, _cause: winit::event::StartCause) {
         // This part is only here to show fps, maybe to debug performance issues.
 
-        let performer_mut = self.performer.as_mut().unwrap();
-
         if self.target_framerate <= self.delta_time.elapsed() {
-            performer_mut.window.clone().unwrap().request_redraw();
+            self.window.as_ref().unwrap().request_redraw();
             self.delta_time = Instant::now();
             self.fps += 1;
             if self.fps_update_time.elapsed().as_millis() > 1000 {
-                performer_mut
-                    .window
-                    .as_mut()
+                self.window
+                    .as_ref()
                     .unwrap()
                     .set_title(&format!("wgpu-text: 'simple' example, FPS: {}", self.fps));
                 self.fps = 0;
@@ -382,12 +480,24 @@ impl<'a> ApplicationHandler<utils::SomethingInFd> for State<'a> {
         let buffer = event.buffer;
         let number_of_elements_in_buffer = event.number_of_elements_in_buffer;
 
-        self.parser.advance(
-            self.performer.as_mut().unwrap(),
-            &buffer[..number_of_elements_in_buffer],
-        );
+        let performer_mut = self.performer.as_mut().unwrap();
+        self.parser
+            .advance(performer_mut, &buffer[..number_of_elements_in_buffer]);
+
+        // Answer the queries that were in the input.
+        let responses = std::mem::take(&mut performer_mut.responses);
+        let mut written = 0;
+        while written < responses.len() {
+            match write(self.pty_fd, &responses[written..]) {
+                Ok(n) => written += n,
+                Err(e) => {
+                    println!("There has been an error writing a response to the master pty: {e}");
+                    break;
+                }
+            }
+        }
 
-        if let Some(window) = self.performer.as_ref().unwrap().window.as_ref() {
+        if let Some(window) = self.window.as_ref() {
             window.request_redraw();
         }
     }
@@ -395,38 +505,42 @@ impl<'a> ApplicationHandler<utils::SomethingInFd> for State<'a> {
 
 impl<'a> State<'a> {
     pub fn new(fd: &'a OwnedFd, state_config: &'a utils::StateConfig) -> Self {
-        let font_color = [0.9, 0.5, 0.5, 1.0];
-
         // Create the parser.
         let parser = Parser::new();
 
-        let font_slice = state_config.font.as_slice();
+        let char_width = Self::char_width(&state_config.font, state_config.font_size);
 
-        // Save the character width of the given font with the given scale in the performer.
-        let font_ref = FontRef::try_from_slice(font_slice).unwrap();
-        let scaled_font = font_ref.as_scaled(state_config.font_size);
-        let char_width = scaled_font.h_advance(font_ref.glyph_id(' '));
+        // Create the terminal state, with as many rows and columns as fit on the screen.
+        let screen = Screen::new(
+            (1080. / state_config.font_size) as usize,
+            (1920. / char_width) as usize,
+            state_config.scrollback_lines,
+        );
 
         // Create the state.
         State {
-            performer: Some(performer::Performer {
-                window: None,
-                font: &state_config.font,
-                brush: None,
-                char_width: char_width,
-                cursor_index: 0,
-                font_size: state_config.font_size,
-                font_color,
-                text_offset_from_left: 20.,
-                text_offset_from_top_as_percentage: 0.02,
-                cursor_section: None,
-                screen: Screen::new(state_config.font_size, char_width, 1920, 1080, 20., 0.02),
-                pty_fd: fd,
-            }),
-            background_color: state_config.background_color,
+            performer: Some(performer::Performer::new(screen)),
+            pty_fd: fd,
+            palette: state_config.palette.clone(),
             parser,
             modifiers: Modifiers::default(),
 
+            window: None,
+            fonts: [
+                &state_config.font,
+                &state_config.bold_font,
+                &state_config.italic_font,
+                &state_config.bold_italic_font,
+            ],
+            brush: None,
+            rectangle_brush: None,
+            font_size: state_config.font_size,
+            char_width,
+            text_offset_from_left: 20.,
+            text_offset_from_top_as_percentage: 0.02,
+            blink_time: Instant::now(),
+            scroll_remainder: 0.,
+
             // FPS and window updating:
             // change '60.0' if you want different FPS cap
             target_framerate: Duration::from_secs_f64(1.0 / 60.0),
@@ -435,6 +549,194 @@ impl<'a> State<'a> {
             fps: 0,
 
             ctx: None,
+            clipboard: None,
+        }
+    }
+
+    /// The width of a single character of the given font with the given scale.
+    fn char_width(font: &[u8], font_size: f32) -> f32 {
+        let font_ref = FontRef::try_from_slice(font).unwrap();
+        let scaled_font = font_ref.as_scaled(font_size);
+        scaled_font.h_advance(font_ref.glyph_id(' '))
+    }
+
+    /// Send the text in the clipboard to the shell. In bracketed paste mode the text is wrapped in the bracketed paste
+    /// markers, so that the application can tell it apart from typed text.
+    fn paste(&mut self) {
+        if self.clipboard.is_none() {
+            match Clipboard::new() {
+                Ok(clipboard) => self.clipboard = Some(clipboard),
+                Err(e) => {
+                    println!("There has been an error opening the clipboard: {e}");
+                    return;
+                }
+            }
+        }
+
+        let text = match self.clipboard.as_mut().unwrap().get_text() {
+            Ok(text) => text,
+            Err(e) => {
+                println!("There has been an error reading the clipboard: {e}");
+                return;
+            }
+        };
+
+        // Remove any markers from the text, so that the pasted text cannot end the bracketed paste early. This is
+        // repeated, since removing a marker can create a new one out of the text around it.
+        let mut text = text;
+        while text.contains("\x1b[200~") || text.contains("\x1b[201~") {
+            text = text.replace("\x1b[200~", "").replace("\x1b[201~", "");
+        }
+
+        // Newlines are sent as carriage returns, like the Enter key.
+        let text = text.replace("\r\n", "\r").replace('\n', "\r");
+
+        let performer_mut = self.performer.as_mut().unwrap();
+        performer_mut.screen.scroll_display_to_bottom();
+
+        let mut bytes = text.into_bytes();
+        if performer_mut.screen.modes.bracketed_paste {
+            bytes.splice(0..0, *b"\x1b[200~");
+            bytes.extend(b"\x1b[201~");
         }
+
+        // The pasted text can be longer than what the pty accepts in a single write.
+        let mut written = 0;
+        while written < bytes.len() {
+            match write(self.pty_fd, &bytes[written..]) {
+                Ok(n) => written += n,
+                Err(e) => {
+                    println!("There has been an error writing to the master pty: {e}");
+                    return;
+                }
+            }
+        }
+    }
+
+    /// The sequence that a numpad key sends in application keypad mode, if it has one.
+    fn application_keypad_sequence(key: &Key) -> Option<&'static [u8]> {
+        let sequence: &[u8] = match key {
+            Key::Named(NamedKey::Enter) => b"\x1bOM",
+            Key::Character(c) => match c.as_str() {
+                "0" => b"\x1bOp",
+                "1" => b"\x1bOq",
+                "2" => b"\x1bOr",
+                "3" => b"\x1bOs",
+                "4" => b"\x1bOt",
+                "5" => b"\x1bOu",
+                "6" => b"\x1bOv",
+                "7" => b"\x1bOw",
+                "8" => b"\x1bOx",
+                "9" => b"\x1bOy",
+                "*" => b"\x1bOj",
+                "+" => b"\x1bOk",
+                "," => b"\x1bOl",
+                "-" => b"\x1bOm",
+                "." => b"\x1bOn",
+                "/" => b"\x1bOo",
+                "=" => b"\x1bOX",
+                _ => return None,
+            },
+            _ => return None,
+        };
+
+        Some(sequence)
+    }
+
+    /// The id of the font in the brush that should be used to draw text with the given attributes.
+    fn font_id(attributes: &Attributes) -> FontId {
+        FontId(attributes.bold as usize + 2 * attributes.italic as usize)
+    }
+
+    /// Build the rectangles (cell backgrounds) and the sections (one per glyph, with the cursor on top) for drawing the
+    /// screen of the performer.
+    fn screen_layout(&self) -> (Vec<Rectangle>, Vec<OwnedSection>) {
+        let screen = &self.performer.as_ref().unwrap().screen;
+        let config = &self.ctx.as_ref().unwrap().config;
+
+        let palette = &self.palette;
+        let blink_visible = self.blink_time.elapsed().as_millis() % 1000 < 500;
+
+        let top = config.height as f32 * self.text_offset_from_top_as_percentage;
+        let cell_position = |row_idx: usize, col_idx: usize| {
+            (
+                self.text_offset_from_left + col_idx as f32 * self.char_width,
+                top + row_idx as f32 * self.font_size,
+            )
+        };
+        let cell_section = |text: &str, color: [f32; 4], font_id: FontId, position: (f32, f32)| {
+            Section::default()
+                .add_text(
+                    Text::new(text)
+                        .with_scale(self.font_size)
+                        .with_color(color)
+                        .with_font_id(font_id),
+                )
+                .with_bounds((config.width as f32 * 0.95, config.height as f32))
+                .with_layout(Layout::default_single_line())
+                .with_screen_position(position)
+                .to_owned()
+        };
+
+        let mut rectangles: Vec<Rectangle> = vec![];
+        let mut sections: Vec<OwnedSection> = vec![];
+        for (row_idx, row) in screen.visible_rows().enumerate() {
+            for (col_idx, cell) in row.iter().enumerate() {
+                let attributes = &cell.attributes;
+                let position = cell_position(row_idx, col_idx);
+
+                let (fg, bg) = if attributes.inverse {
+                    (cell.bg, cell.fg)
+                } else {
+                    (cell.fg, cell.bg)
+                };
+                // Cells with the default background color do not need a rectangle, they are drawn by clearing the window.
+                if bg != Color::DefaultBackground {
+                    rectangles.push(Rectangle {
+                        position,
+                        size: (self.char_width, self.font_size),
+                        color: palette.resolve(bg),
+                    });
+                }
+
+                let mut color = palette.resolve(fg);
+                if attributes.dim {
+                    color[3] *= 0.5;
+                }
+
+                if attributes.hidden || (attributes.blink && !blink_visible) {
+                    continue;
+                }
+                // The glyph of a double-width character covers its spacer cell as well.
+                if (cell.c != ' ' || !cell.combining.is_empty()) && !cell.spacer {
+                    sections.push(cell_section(
+                        &cell.grapheme(),
+                        color,
+                        Self::font_id(attributes),
+                        position,
+                    ));
+                }
+                if attributes.underline {
+                    sections.push(cell_section("_", color, FontId(0), position));
+                }
+                if attributes.strikethrough {
+                    sections.push(cell_section("─", color, FontId(0), position));
+                }
+            }
+        }
+
+        // NOTE: The cursor section has to be the last one in the brush queue, so that it stays on top of the text.
+        // The cursor is not drawn if it has been scrolled off the display.
+        let cursor_row = screen.row_index + screen.display_offset;
+        if screen.modes.show_cursor && cursor_row < screen.num_rows() {
+            sections.push(cell_section(
+                "█",
+                palette.cursor,
+                FontId(0),
+                cell_position(cursor_row, screen.column_index),
+            ));
+        }
+
+        (rectangles, sections)
     }
 }
@@ -15,7 +15,7 @@ use nix::unistd::read;
 
 use serde::Deserialize;
 
-use crate::performer::Performer;
+use crate::color::Palette;
 
 #[derive(Clone, Debug)]
 pub struct SomethingInFd {
@@ -23,20 +23,40 @@ pub struct SomethingInFd {
     pub number_of_elements_in_buffer: usize,
 }
 
-// The Config struct, used to read from a config file.
+// The Config struct, used to read from a config file. Fields missing from the config file get their default values.
 #[derive(Debug, Deserialize)]
+#[serde(default)]
 pub struct Config {
     pub font_name: String,
+    // The fonts used for bold, italic and bold italic text. If they are not set, the DejaVu variants are used with the
+    // default font and the regular font is used with any other font.
+    pub bold_font_name: Option<String>,
+    pub italic_font_name: Option<String>,
+    pub bold_italic_font_name: Option<String>,
     pub font_size: f32,
     pub background_color: [f64; 4],
+    pub foreground_color: [f32; 4],
+    pub cursor_color: [f32; 4],
+    // The 16 ANSI colors: black, red, green, yellow, blue, magenta, cyan, white and then their bright variants.
+    pub ansi_colors: [[f32; 4]; 16],
+    pub scrollback_lines: usize,
 }
 
+const DEFAULT_FONT_NAME: &str = "fonts/DejaVuSansMono.ttf";
+
 impl Default for Config {
     fn default() -> Self {
         Config {
-            font_name: String::from("fonts/DejaVuSansMono.ttf"),
+            font_name: String::from(DEFAULT_FONT_NAME),
+            bold_font_name: None,
+            italic_font_name: None,
+            bold_italic_font_name: None,
             font_size: 32.0,
-            background_color: [0.38, 0.235, 0.027, 1.]
+            background_color: [0.38, 0.235, 0.027, 1.],
+            foreground_color: Palette::default().foreground,
+            cursor_color: Palette::default().cursor,
+            ansi_colors: Palette::default().ansi_colors,
+            scrollback_lines: 10000,
         }
     }
 }
@@ -50,11 +70,15 @@ impl Config {
 }
 
 // The StateConfig struct, uses the Config to store State-specific config data.
-#[derive(Debug, Deserialize)]
+#[derive(Debug)]
 pub struct StateConfig {
     pub font_size: f32,
     pub font: Vec<u8>,
-    pub background_color: [f64; 4],
+    pub bold_font: Vec<u8>,
+    pub italic_font: Vec<u8>,
+    pub bold_italic_font: Vec<u8>,
+    pub palette: Palette,
+    pub scrollback_lines: usize,
 }
 
 impl StateConfig {
@@ -64,12 +88,42 @@ impl StateConfig {
         let config_path = expand_tilde("~/.config/rustole/rustole.toml");
         let config = Config::from_file(Path::new(&config_path));
 
+        let uses_default_font = config.font_name == DEFAULT_FONT_NAME;
         let font = fs::read(config.font_name).unwrap();
 
+        // Read a font variant, falling back to the regular font if it is not set or cannot be read.
+        let read_variant = |font_name: Option<String>, default_font_name: &str| {
+            let font_name = match font_name {
+                Some(font_name) => font_name,
+                None if uses_default_font => String::from(default_font_name),
+                None => return font.clone(),
+            };
+
+            match fs::read(&font_name) {
+                Ok(variant) => variant,
+                Err(e) => {
+                    println!("There has been an error reading the font {font_name}: {e}");
+                    font.clone()
+                }
+            }
+        };
+
         StateConfig {
             font_size: config.font_size,
+            bold_font: read_variant(config.bold_font_name, "fonts/DejaVuSansMono-Bold.ttf"),
+            italic_font: read_variant(config.italic_font_name, "fonts/DejaVuSansMono-Oblique.ttf"),
+            bold_italic_font: read_variant(
+                config.bold_italic_font_name,
+                "fonts/DejaVuSansMono-BoldOblique.ttf",
+            ),
             font,
-            background_color: config.background_color
+            palette: Palette {
+                foreground: config.foreground_color,
+                background: config.background_color.map(|component| component as f32),
+                cursor: config.cursor_color,
+                ansi_colors: config.ansi_colors,
+            },
+            scrollback_lines: config.scrollback_lines,
         }
     }
 }
@@ -80,7 +134,10 @@ pub fn spawn_pty_with_shell(default_shell: String) -> OwnedFd {
             Ok(fork_pty_res) => match fork_pty_res {
                 ForkptyResult::Parent { child: _, master } => master,
                 ForkptyResult::Child => {
-                    let _ = Command::new(&default_shell).exec();
+                    // Let the applications know that we support 24-bit colors.
+                    let _ = Command::new(&default_shell)
+                        .env("COLORTERM", "truecolor")
+                        .exec();
                     panic!("exec() failed!");
                 }
             },
@@ -139,25 +196,11 @@ pub fn monitor_fd(fd: OwnedFd, proxy: EventLoopProxy<SomethingInFd>) {
 }
 
 pub fn expand_tilde(path: &str) -> String {
-    if path.starts_with("~") {
+    if let Some(rest) = path.strip_prefix("~") {
         let mut resulting_path = std::env::var("HOME").unwrap();
-        resulting_path.push_str(&path[1..]); // Remove the ~ and join the rest of the path
+        resulting_path.push_str(rest); // Remove the ~ and join the rest of the path
         resulting_path
     } else {
         String::from(path) // No tilde, return path as-is
     }
 }
-
-pub fn move_cursor_right(performer_mut: &mut Performer) {
-    let cursor_section = performer_mut.cursor_section.as_mut().unwrap();
-    let char_width = performer_mut.char_width;
-
-    cursor_section.screen_position.0 += char_width;
-}
-
-pub fn move_cursor_left(performer_mut: &mut Performer) {
-    let cursor_section = performer_mut.cursor_section.as_mut().unwrap();
-    let char_width = performer_mut.char_width;
-
-    cursor_section.screen_position.0 -= char_width;
-}